@@ -0,0 +1,106 @@
+//! Answers are values for template variables provided up front,
+//! allowing templates to be applied without prompts
+
+use super::defaults;
+use errors::{ErrorKind, Result, ResultExt};
+use serde_json;
+use serde_yaml;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+pub type Answers = BTreeMap<String, String>;
+
+/// parses answers from a target file. files ending in .json and
+/// .yml or .yaml are parsed as json and yaml objects respectively,
+/// all other files are parsed as env files of key/value pairs
+pub fn from_file<P>(path: P) -> Result<Answers>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let extension = path.extension().and_then(|ext| ext.to_str());
+    let answers = match extension {
+        Some("json") => from_json(&fs::read_to_string(path)?),
+        Some("yml") | Some("yaml") => from_yaml(&fs::read_to_string(path)?),
+        _ => defaults::from_file(path).map(|pairs| pairs.into_iter().collect()),
+    };
+    answers.chain_err(|| {
+        format!("failed to parse answers from file {}", path.display())
+    })
+}
+
+/// parses answers from a json object
+pub fn from_json(s: &str) -> Result<Answers> {
+    let values: BTreeMap<String, serde_json::Value> =
+        serde_json::from_str(s)?;
+    values
+        .into_iter()
+        .map(|(key, value)| {
+            let value = match value {
+                serde_json::Value::String(s) => s,
+                serde_json::Value::Null => String::new(),
+                serde_json::Value::Bool(b) => b.to_string(),
+                serde_json::Value::Number(n) => n.to_string(),
+                _ => return Err(not_scalar(key)),
+            };
+            Ok((key, value))
+        })
+        .collect()
+}
+
+/// parses answers from a yaml mapping
+pub fn from_yaml(s: &str) -> Result<Answers> {
+    let values: BTreeMap<String, serde_yaml::Value> =
+        serde_yaml::from_str(s)?;
+    values
+        .into_iter()
+        .map(|(key, value)| {
+            let value = match value {
+                serde_yaml::Value::String(s) => s,
+                serde_yaml::Value::Null => String::new(),
+                serde_yaml::Value::Bool(b) => b.to_string(),
+                serde_yaml::Value::Number(n) => n.to_string(),
+                _ => return Err(not_scalar(key)),
+            };
+            Ok((key, value))
+        })
+        .collect()
+}
+
+fn not_scalar(key: String) -> ::errors::Error {
+    ErrorKind::InvalidValue(
+        key,
+        "expected a string, boolean or number".to_owned(),
+    ).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_json() {
+        let answers =
+            from_json(r#"{"name": "bars", "docker": true, "port": 8080}"#)
+                .unwrap();
+        assert_eq!(answers["name"], "bars");
+        assert_eq!(answers["docker"], "true");
+        assert_eq!(answers["port"], "8080");
+    }
+
+    #[test]
+    fn test_from_yaml() {
+        let answers = from_yaml("name: bars\ndocker: false\nport: 8080\n")
+            .unwrap();
+        assert_eq!(answers["name"], "bars");
+        assert_eq!(answers["docker"], "false");
+        assert_eq!(answers["port"], "8080");
+    }
+
+    #[test]
+    fn test_nested_values_are_invalid() {
+        assert!(from_json(r#"{"name": {"first": "bars"}}"#).is_err());
+        assert!(from_yaml("name: [bars]").is_err());
+    }
+}
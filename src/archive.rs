@@ -0,0 +1,154 @@
+//! Templates distributed as `.tar.gz` or `.zip` archives
+
+use errors::{Result, ResultExt};
+use flate2::read::GzDecoder;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use tar;
+use zip::ZipArchive;
+
+/// file extensions of supported archives
+pub const EXTENSIONS: &[&str] = &[".tar.gz", ".tgz", ".zip"];
+
+/// true if a path names a supported archive
+pub fn is_archive(path: &str) -> bool {
+    EXTENSIONS.iter().any(|ext| path.ends_with(ext))
+}
+
+/// extracts an archive to a directory, returning the directory the
+/// template was extracted to. archives whose contents are wrapped in a
+/// single top level directory, such as release artifacts, resolve to
+/// that directory
+pub fn extract<A, D>(archive: A, dir: D) -> Result<PathBuf>
+where
+    A: AsRef<Path>,
+    D: AsRef<Path>,
+{
+    let (archive, dir) = (archive.as_ref(), dir.as_ref());
+    unpack(archive, dir).chain_err(|| {
+        format!("failed to extract archive {}", archive.display())
+    })?;
+    let entries = fs::read_dir(dir)?.collect::<io::Result<Vec<_>>>()?;
+    match entries.as_slice() {
+        [entry] if entry.path().is_dir() => Ok(entry.path()),
+        _ => Ok(dir.to_path_buf()),
+    }
+}
+
+fn unpack(archive: &Path, dir: &Path) -> Result<()> {
+    let file = File::open(archive)?;
+    if archive.to_string_lossy().ends_with(".zip") {
+        let mut zip = ZipArchive::new(file).map_err(io::Error::other)?;
+        for i in 0..zip.len() {
+            let mut entry = zip.by_index(i).map_err(io::Error::other)?;
+            // entries with paths outside of the archive are skipped
+            let path = match entry.enclosed_name() {
+                Some(path) => dir.join(path),
+                None => continue,
+            };
+            if entry.is_dir() {
+                fs::create_dir_all(&path)?;
+            } else {
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                io::copy(&mut entry, &mut File::create(&path)?)?;
+                set_mode(&path, entry.unix_mode())?;
+            }
+        }
+    } else {
+        tar::Archive::new(GzDecoder::new(file)).unpack(dir)?;
+    }
+    Ok(())
+}
+
+/// applies the permissions a zip entry records, such as the executable
+/// bit of scripts, on platforms supporting them
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: Option<u32>) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    match mode {
+        Some(mode) => fs::set_permissions(path, fs::Permissions::from_mode(mode & 0o7777)),
+        None => Ok(()),
+    }
+}
+
+#[cfg(not(unix))]
+fn set_mode(_: &Path, _: Option<u32>) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common;
+    use std::io::Write;
+    use tempdir::TempDir;
+    use zip::write::{FileOptions, ZipWriter};
+
+    const FILES: &[(&str, &str)] = &[
+        ("tpl-v1/default.env", "name=bars"),
+        ("tpl-v1/template/hello", "hi {{name}}"),
+    ];
+
+    fn assert_extracted(archive: &Path) {
+        let dir = TempDir::new("porteurbars-archive").unwrap();
+        let root = extract(archive, dir.path()).unwrap();
+        assert_eq!(root, dir.path().join("tpl-v1"));
+        assert_eq!(
+            fs::read_to_string(root.join("template/hello")).unwrap(),
+            "hi {{name}}"
+        );
+    }
+
+    #[test]
+    fn test_is_archive() {
+        assert!(is_archive("./tpl.tar.gz"));
+        assert!(is_archive("/tpl.zip"));
+        assert!(!is_archive("./tpl"));
+    }
+
+    #[test]
+    fn test_extract_tar_gz() {
+        let tmp = TempDir::new("porteurbars-test").unwrap();
+        let path = tmp.path().join("tpl.tar.gz");
+        common::tar_gz(&path, FILES);
+        assert_extracted(&path);
+    }
+
+    #[test]
+    fn test_extract_zip() {
+        let tmp = TempDir::new("porteurbars-test").unwrap();
+        let path = tmp.path().join("tpl.zip");
+        let mut zip = ZipWriter::new(File::create(&path).unwrap());
+        for &(name, content) in FILES {
+            zip.start_file(name, FileOptions::default()).unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+        assert_extracted(&path);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_extract_zip_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+        let tmp = TempDir::new("porteurbars-test").unwrap();
+        let path = tmp.path().join("tpl.zip");
+        let mut zip = ZipWriter::new(File::create(&path).unwrap());
+        for &(name, mode) in &[("run.sh", 0o755), ("README", 0o644)] {
+            zip.start_file(name, FileOptions::default().unix_permissions(mode))
+                .unwrap();
+            zip.write_all(b"content").unwrap();
+        }
+        zip.finish().unwrap();
+        let dir = TempDir::new("porteurbars-archive").unwrap();
+        extract(&path, dir.path()).unwrap();
+        let mode = |name: &str| {
+            fs::metadata(dir.path().join(name)).unwrap().permissions().mode() & 0o777
+        };
+        assert_eq!(mode("run.sh"), 0o755);
+        assert_eq!(mode("README"), 0o644);
+    }
+}
@@ -0,0 +1,195 @@
+//! Authentication of requests to private template repositories
+
+use git2::{self, Cred, CredentialType};
+use std::cell::{Cell, RefCell};
+use std::env;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// environment variables holding a private ssh key file and its passphrase
+const SSH_KEY: &str = "PORTEURBARS_SSH_KEY";
+const SSH_PASSPHRASE: &str = "PORTEURBARS_SSH_PASSPHRASE";
+
+/// environment variables holding https access tokens, in order of precedence
+const TOKENS: &[&str] = &["PORTEURBARS_TOKEN", "GITHUB_TOKEN"];
+
+/// username paired with tokens when a url does not name one
+const TOKEN_USER: &str = "x-access-token";
+
+/// A way of authenticating with a remote
+#[derive(Debug, PartialEq)]
+enum Method {
+    Username,
+    SshKey(PathBuf, Option<String>, &'static str),
+    SshAgent,
+    Token(&'static str, String),
+    CredentialHelper,
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Method::Username => write!(f, "username"),
+            Method::SshKey(ref path, _, origin) => {
+                write!(f, "ssh key {} ({})", path.display(), origin)
+            }
+            Method::SshAgent => write!(f, "ssh-agent"),
+            Method::Token(var, _) => write!(f, "token from ${}", var),
+            Method::CredentialHelper => write!(f, "git credential helper"),
+        }
+    }
+}
+
+/// methods of authenticating a remote allows, in the order they're tried,
+/// given a configured ssh key and a lookup of environment variables.
+/// a key named in the environment takes precedence over a configured one
+fn methods<F>(allowed: CredentialType, ssh_key: Option<&Path>, var: F) -> Vec<Method>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut methods = Vec::new();
+    if allowed.contains(CredentialType::USERNAME) {
+        methods.push(Method::Username);
+    }
+    if allowed.contains(CredentialType::SSH_KEY) {
+        let key = var(SSH_KEY)
+            .map(|key| (key.into(), "$PORTEURBARS_SSH_KEY"))
+            .or_else(|| ssh_key.map(|key| (key.to_owned(), "ssh_key in config.toml")));
+        if let Some((key, origin)) = key {
+            methods.push(Method::SshKey(key, var(SSH_PASSPHRASE), origin));
+        }
+        methods.push(Method::SshAgent);
+    }
+    if allowed.contains(CredentialType::USER_PASS_PLAINTEXT) {
+        for name in TOKENS {
+            if let Some(token) = var(name) {
+                methods.push(Method::Token(name, token));
+            }
+        }
+        methods.push(Method::CredentialHelper);
+    }
+    methods
+}
+
+/// Credentials for a connection to a remote, recording which methods
+/// were tried and whether all of them were rejected
+#[derive(Default)]
+pub struct Credentials {
+    ssh_key: Option<PathBuf>,
+    tried: Rc<RefCell<Vec<String>>>,
+    exhausted: Rc<Cell<bool>>,
+}
+
+impl Credentials {
+    /// credentials which try a configured ssh key file, if any, before
+    /// an ssh-agent
+    pub fn new(ssh_key: Option<PathBuf>) -> Credentials {
+        Credentials {
+            ssh_key,
+            ..Credentials::default()
+        }
+    }
+
+    /// callbacks supplying credentials to git. each method is tried
+    /// at most once, as git asks again for every rejected credential
+    pub fn callbacks<'a>(&self) -> git2::RemoteCallbacks<'a> {
+        let ssh_key = self.ssh_key.clone();
+        let tried = self.tried.clone();
+        let exhausted = self.exhausted.clone();
+        let mut cb = git2::RemoteCallbacks::new();
+        cb.credentials(move |url, url_username, allowed| {
+            let username = url_username.unwrap_or("git");
+            let var = |name: &str| env::var(name).ok().filter(|v| !v.is_empty());
+            for method in methods(allowed, ssh_key.as_deref(), var) {
+                let name = method.to_string();
+                if tried.borrow().contains(&name) {
+                    continue;
+                }
+                tried.borrow_mut().push(name);
+                debug!("authenticating {} with {}", url, method);
+                let cred = match method {
+                    Method::Username => Cred::username(username),
+                    Method::SshKey(ref path, ref passphrase, _) => Cred::ssh_key(
+                        username,
+                        None,
+                        path,
+                        passphrase.as_ref().map(String::as_str),
+                    ),
+                    Method::SshAgent => Cred::ssh_key_from_agent(username),
+                    Method::Token(_, ref token) => Cred::userpass_plaintext(
+                        url_username.unwrap_or(TOKEN_USER),
+                        token,
+                    ),
+                    Method::CredentialHelper => {
+                        git2::Config::open_default().and_then(|cfg| {
+                            Cred::credential_helper(&cfg, url, url_username)
+                        })
+                    }
+                };
+                match cred {
+                    Ok(cred) => return Ok(cred),
+                    Err(e) => debug!("failed to authenticate with {}: {}", method, e),
+                }
+            }
+            exhausted.set(true);
+            Err(git2::Error::from_str("no authentication available"))
+        });
+        cb
+    }
+
+    /// whether every method allowed was tried and rejected
+    pub fn exhausted(&self) -> bool {
+        self.exhausted.get()
+    }
+
+    /// the methods of authentication tried, excluding usernames
+    pub fn tried(&self) -> Vec<String> {
+        let username = Method::Username.to_string();
+        self.tried
+            .borrow()
+            .iter()
+            .filter(|method| **method != username)
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_methods() {
+        let var = |name: &str| match name {
+            "PORTEURBARS_SSH_KEY" => Some("/keys/id_ed25519".to_owned()),
+            "GITHUB_TOKEN" => Some("secret".to_owned()),
+            _ => None,
+        };
+        assert_eq!(
+            methods(CredentialType::SSH_KEY, None, var),
+            vec![
+                Method::SshKey("/keys/id_ed25519".into(), None, "$PORTEURBARS_SSH_KEY"),
+                Method::SshAgent,
+            ]
+        );
+        assert_eq!(
+            methods(CredentialType::SSH_KEY, Some(Path::new("/keys/deploy")), |_| None),
+            vec![
+                Method::SshKey("/keys/deploy".into(), None, "ssh_key in config.toml"),
+                Method::SshAgent,
+            ]
+        );
+        assert_eq!(
+            methods(CredentialType::USER_PASS_PLAINTEXT, None, var),
+            vec![
+                Method::Token("GITHUB_TOKEN", "secret".to_owned()),
+                Method::CredentialHelper,
+            ]
+        );
+        assert_eq!(
+            methods(CredentialType::SSH_KEY, None, |_| None),
+            vec![Method::SshAgent]
+        );
+    }
+}
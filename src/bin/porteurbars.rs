@@ -1,37 +1,183 @@
+#![allow(clippy::result_large_err)]
+
 #[macro_use]
 extern crate log;
 extern crate env_logger;
 extern crate clap;
 extern crate porteurbars;
-extern crate tempdir;
 
-use clap::{App, Arg, ArgMatches};
-use porteurbars::{Result, Template};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use porteurbars::{Change, Options, Result, Template};
+use porteurbars::answers::Answers;
 use porteurbars::git;
-use tempdir::TempDir;
+use porteurbars::metadata::Metadata;
+use porteurbars::source;
+use std::env;
+
+/// options for applying templates, with answers taking precedence
+/// over a given set of previous answers
+fn options(args: &ArgMatches, previous: Answers) -> Result<Options> {
+    let mut answers = previous;
+    if let Some(path) = args.value_of("answers") {
+        answers.extend(porteurbars::answers::from_file(path)?);
+    }
+    Ok(Options {
+        yes: args.occurrences_of("yes") > 0,
+        keep: args.occurrences_of("keep") > 0,
+        dry_run: args.occurrences_of("dry_run") > 0,
+        trust_hooks: args.occurrences_of("trust_hooks") > 0,
+        offline: args.occurrences_of("offline") > 0,
+        answers,
+        // variables which aren't unicode can't be template values
+        env: env::vars_os()
+            .filter_map(|(k, v)| Some((k.into_string().ok()?, v.into_string().ok()?)))
+            .collect(),
+        seed: args.value_of("seed").map(|seed| seed.parse().unwrap()),
+    })
+}
 
-fn run(args: ArgMatches) -> Result<()> {
+fn report(changes: Vec<Change>, all: bool) {
+    for change in changes {
+        match change {
+            Change::Unchanged(_) if !all => (),
+            _ => println!("{}", change),
+        }
+    }
+}
+
+fn run(args: &ArgMatches) -> Result<()> {
     let repo = args.value_of("repository").unwrap();
-    let url = porteurbars::git::Url::from_str(repo)?;
+    let reference = git::Reference::from_str(repo)?;
+    let url = reference.url;
     let target = args.value_of("target").unwrap_or(".");
-    let root = args.value_of("template_root");
-    let revision = args.value_of("rev").unwrap_or("master");
-    let yes = args.occurrences_of("yes") > 0;
-    let replace = args.occurrences_of("keep") > 0;
-    info!("Cloning...");
-    let tmp = TempDir::new("porteurbars")?;
-    git::clone(url, &tmp, revision)?;
+    // flags take precedence over a subdirectory and revision in the uri
+    let root = args.value_of("base").or(reference.base.as_deref());
+    let revision = args.value_of("rev").or(reference.rev.as_deref());
+    let options = options(args, Answers::new())?;
+    let offline = options.offline;
+    // local directories are used in place rather than cloned
+    // and archives are extracted
+    let git = !matches!(url, git::Url::Directory(_) | git::Url::Archive(_));
+    if !git && revision.is_some() {
+        warn!("ignoring revision for template outside of git");
+    }
+    let source = source::from_url(url, revision, root, offline);
+    info!("Fetching...");
+    let template = Template::fetch(&*source)?;
     info!("Applying template...");
-    Template::new(&tmp).apply(target, root, yes, replace)?;
-    println!("off you go");
+    let applied = template.apply(target, root, &options)?;
+    if options.dry_run {
+        report(applied.changes, true);
+    } else {
+        println!("off you go");
+    }
+    Ok(())
+}
+
+fn update(args: &ArgMatches) -> Result<()> {
+    let target = args.value_of("target").unwrap_or(".");
+    let metadata = Metadata::read(target)?;
+    let url = git::Url::from_str(&metadata.url)?;
+    // there's no previous version of a directory or archive to merge
+    // changes from
+    if let git::Url::Directory(_) | git::Url::Archive(_) = url {
+        return Err(format!(
+            "template {} is a local directory or archive. only templates in git repositories can be updated",
+            metadata.url
+        ).into());
+    }
+    let root = metadata.base.as_deref();
+    let previous_commit = metadata.commit.as_ref().ok_or(
+        "template metadata does not record a commit to update from",
+    )?;
+    let revision = args.value_of("rev").or(metadata.rev.as_deref());
+    let options = options(args, metadata.context.clone())?;
+    let offline = options.offline;
+    let source = |rev: Option<&str>| source::Git {
+        url: url.clone(),
+        rev: rev.map(str::to_owned),
+        base: root.map(str::to_owned),
+        offline,
+    };
+    info!("Fetching...");
+    let previous = Template::fetch(&source(Some(previous_commit)))?;
+    let next = Template::fetch(&source(revision))?;
+    info!("Updating template...");
+    let applied = next.update(
+        &previous,
+        &metadata.context,
+        &metadata.includes,
+        target,
+        root,
+        &options,
+    )?;
+    report(applied.changes, options.dry_run);
     Ok(())
 }
 
+/// arguments shared by commands applying templates
+fn apply_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("target").value_name("target").help(
+            "directory to write template output to. defaults to current working directory",
+        ),
+        Arg::with_name("rev")
+            .short("r")
+            .long("rev")
+            .value_name("revision")
+            .takes_value(true)
+            .help(
+                "git branch, tag, commit or semver requirement matching v* tags to checkout. defaults to the repository's default branch",
+            ),
+        Arg::with_name("answers")
+            .short("a")
+            .long("answers")
+            .value_name("answers_file")
+            .takes_value(true)
+            .help(
+                "env, json or yaml file of values taking precedence over your environment and template defaults",
+            ),
+        Arg::with_name("yes")
+            .short("y")
+            .long("yes")
+            .takes_value(false)
+            .help("disables value prompts by accepting all default values"),
+        Arg::with_name("keep")
+            .short("k")
+            .long("keep")
+            .takes_value(false)
+            .help(
+                "disables replacement prompts and keeps local copies of files",
+            ),
+        Arg::with_name("dry_run")
+            .long("dry-run")
+            .takes_value(false)
+            .help(
+                "renders the template without writing files, reporting what would change",
+            ),
+        Arg::with_name("offline")
+            .long("offline")
+            .takes_value(false)
+            .help(
+                "uses previously cached templates without fetching from the network",
+            ),
+        Arg::with_name("seed")
+            .long("seed")
+            .value_name("seed")
+            .takes_value(true)
+            .validator(|seed| seed.parse::<u64>().map(|_| ()).map_err(|e| e.to_string()))
+            .help(
+                "integer seeding the uuid and random_hex helpers so they render the same values every time",
+            ),
+    ]
+}
+
 fn main() {
     env_logger::init();
     let args = App::new(env!("CARGO_PKG_NAME"))
         .version(env!("CARGO_PKG_VERSION"))
         .about("portable git hosted project templates")
+        .setting(AppSettings::SubcommandsNegateReqs)
         .arg(
             Arg::with_name("repository")
                 .value_name("repository")
@@ -40,13 +186,15 @@ fn main() {
                     "uri of template to apply.
 example uris
 github: user/repo
+gitlab: gl:group/repo
+subdir: user/repo//path/to/template@v2
+   dir: ./path/to/template
+   zip: ./path/to/template.zip
  local: file:///path/to/repo
    git: git@github.com:user/repo.git",
                 ),
         )
-        .arg(Arg::with_name("target").value_name("target").help(
-            "directory to write template output to. defaults to current working directory",
-        ))
+        .args(&apply_args())
         .arg(
             Arg::with_name("base")
                 .short("b")
@@ -58,33 +206,28 @@ github: user/repo
                 ),
         )
         .arg(
-            Arg::with_name("rev")
-                .short("r")
-                .long("rev")
-                .value_name("revision")
-                .takes_value(true)
-                .help("git revision to checkout. defaults to 'master'"),
-        )
-        .arg(
-            Arg::with_name("yes")
-                .short("y")
-                .long("yes")
-                .takes_value(false)
-                .help("disables value prompts by accepting all default values"),
-        )
-        .arg(
-            Arg::with_name("keep")
-                .short("k")
-                .long("keep")
+            Arg::with_name("trust_hooks")
+                .long("trust-hooks")
                 .takes_value(false)
                 .help(
-                    "disables replacement prompts and keeps local copies of files",
+                    "runs a template's pre and post generation hooks without confirmation",
                 ),
         )
+        .subcommand(
+            SubCommand::with_name("update")
+                .about(
+                    "merges changes to a previously applied template with local changes",
+                )
+                .args(&apply_args()),
+        )
         .get_matches();
 
+    let result = match args.subcommand() {
+        ("update", Some(args)) => update(args),
+        _ => run(&args),
+    };
 
-    if let Err(ref e) = run(args) {
+    if let Err(ref e) = result {
         use std::io::Write;
         let stderr = &mut ::std::io::stderr();
         let errmsg = "Error writing to stderr";
@@ -0,0 +1,106 @@
+//! User configuration, read from `$XDG_CONFIG_HOME/porteurbars/config.toml`
+//! falling back on `~/.config/porteurbars/config.toml`
+
+use errors::{Error, ErrorKind, Result, ResultExt};
+use std::collections::BTreeMap;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml;
+
+/// name of the configuration file within the configuration directory
+const FILE: &str = "config.toml";
+
+#[derive(Debug, Default, Deserialize, PartialEq)]
+pub struct Config {
+    /// host aliases mapped to the base urls of repositories they host,
+    /// used in `alias:owner/repo` template uris
+    #[serde(default)]
+    pub hosts: BTreeMap<String, String>,
+    /// private ssh key file used for ssh urls, unless one is named by
+    /// `PORTEURBARS_SSH_KEY`
+    pub ssh_key: Option<PathBuf>,
+}
+
+/// directory user configuration is kept in
+pub fn dir() -> Result<PathBuf> {
+    dir_from(|name| env::var_os(name))
+}
+
+/// resolves the configuration directory with a given environment lookup
+fn dir_from<F>(var: F) -> Result<PathBuf>
+where
+    F: Fn(&str) -> Option<OsString>,
+{
+    var("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| var("HOME").map(|home| Path::new(&home).join(".config")))
+        .map(|dir| dir.join("porteurbars"))
+        .ok_or_else(|| ErrorKind::Homeless.into())
+}
+
+/// path of the user configuration file
+pub fn file() -> Result<PathBuf> {
+    Ok(dir()?.join(FILE))
+}
+
+impl Config {
+    /// loads user configuration, if any
+    pub fn load() -> Result<Config> {
+        Config::load_from(|name| env::var_os(name))
+    }
+
+    /// loads user configuration with a given environment lookup. without a
+    /// configuration directory there is nothing to load, so defaults apply
+    fn load_from<F>(var: F) -> Result<Config>
+    where
+        F: Fn(&str) -> Option<OsString>,
+    {
+        let file = match dir_from(var) {
+            Ok(dir) => dir.join(FILE),
+            Err(Error(ErrorKind::Homeless, _)) => return Ok(Config::default()),
+            Err(e) => return Err(e),
+        };
+        if !file.exists() {
+            return Ok(Config::default());
+        }
+        Config::from_string(&fs::read_to_string(&file)?).chain_err(|| {
+            format!("failed to parse config from file {}", file.display())
+        })
+    }
+
+    /// parses configuration from a string of toml
+    pub fn from_string(s: &str) -> Result<Config> {
+        Ok(toml::from_str(s)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_string() {
+        let config = Config::from_string(
+            r#"
+ssh_key = "/keys/deploy"
+
+[hosts]
+work = "https://git.example.com"
+"#,
+        ).unwrap();
+        assert_eq!(
+            config.hosts.get("work"),
+            Some(&"https://git.example.com".to_owned())
+        );
+        assert_eq!(config.ssh_key, Some(PathBuf::from("/keys/deploy")));
+        assert_eq!(Config::from_string("").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn test_load_without_home() {
+        assert_eq!(Config::load_from(|_| None).unwrap(), Config::default());
+    }
+}
@@ -23,7 +23,7 @@ pub fn from_string(s: String) -> Defaults {
         .map(|l| l.splitn(2, "=").collect::<Vec<_>>())
         .fold(Vec::new(), |mut acc, pair| {
             if pair.len() == 2 {
-                if let Some(value) = pair[1].splitn(2, "#").next() {
+                if let Some(value) = pair[1].split('#').next() {
                     acc.push(
                         (pair[0].trim().to_owned(), value.trim().to_owned()),
                     );
@@ -1,7 +1,12 @@
+#![allow(deprecated, unused_doc_comments)]
+
 
 use git2;
 use handlebars::{RenderError, TemplateError, TemplateRenderError};
+use serde_json;
+use serde_yaml;
 use std::io;
+use toml;
 
 
 /// Enumeration of types of errors
@@ -13,6 +18,9 @@ error_chain! {
         Template(TemplateError);
         TemplateRender(TemplateRenderError);
         Git(git2::Error);
+        Toml(toml::de::Error);
+        Json(serde_json::Error);
+        Yaml(serde_yaml::Error);
     }
 
     errors {
@@ -24,6 +32,22 @@ error_chain! {
             description("home directory not defined")
             display("home directory not defined")
         }
+        InvalidValue(name: String, reason: String) {
+            description("invalid value")
+            display("invalid value for {}: {}", name, reason)
+        }
+        HookFailed(command: String, status: String) {
+            description("hook failed")
+            display("hook `{}` failed with {}", command, status)
+        }
+        InvalidBase(path: String, reason: String) {
+            description("invalid template base directory")
+            display("invalid template base directory {}: {}", path, reason)
+        }
+        Unauthenticated(url: String, tried: String) {
+            description("authentication failed")
+            display("failed to authenticate with {}. tried {}", url, tried)
+        }
         InvalidUri(t: String) {
             description("invalid uri")
             display("invalid template uri {}", t)
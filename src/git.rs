@@ -1,30 +1,69 @@
 extern crate git2;
 extern crate regex;
-use errors::{ErrorKind, Result, ResultExt};
+use archive;
+use auth::Credentials;
+use config::Config;
+use errors::{Error, ErrorKind, Result, ResultExt};
+use fs2::FileExt;
 
-use git2::build::RepoBuilder;
+use git2::build::CheckoutBuilder;
 use regex::Regex;
-use std::path::Path;
+use semver::{Version, VersionReq};
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum Url {
+    /// a template directory on the local filesystem, used in place
+    Directory(String),
+    /// a `.tar.gz` or `.zip` template archive on the local filesystem
+    Archive(String),
     Local(String),
     Github(String, String),
+    /// a repository path on a host referred to by an alias,
+    /// such as `gl:owner/repo`
+    Shorthand(String, String),
     Remote(String),
 }
 
+/// base urls of hosts with built in aliases
+const HOSTS: &[(&str, &str)] = &[
+    ("gh", "https://github.com"),
+    ("gl", "https://gitlab.com"),
+    ("bb", "https://bitbucket.org"),
+];
+
 impl Url {
-    /// supports two types git of repository urls
-    /// local repositories that start with file://
-    /// and github repositories ower/repo
+    /// supports local template directories, which start with
+    /// `.`, `/` or a windows drive, `.tar.gz` and `.zip` archives at such
+    /// paths or file:// urls, and three types git of repository urls
+    /// local repositories that start with file://,
+    /// github repositories ower/repo
+    /// and repositories on aliased hosts alias:owner/repo
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(txt: &str) -> Result<Url> {
         lazy_static! {
+            static ref DIRECTORY: Regex =
+                Regex::new(r#"^(\.{1,2}|\.{1,2}[/\\].*|/.*|[A-Za-z]:[/\\].*)$"#)
+                    .unwrap();
             static ref LOCAL: Regex = Regex::new(r#"^file://(\S+)$"#).unwrap();
             static ref GH: Regex = Regex::new(r#"^([^\s/]+)/([^\s/]+?)$"#).unwrap();
+            static ref SHORTHAND: Regex =
+                Regex::new(r#"^([A-Za-z][\w-]*):([^\s:/][^\s:]*?)/?$"#).unwrap();
             static ref REMOTE: Regex = Regex::new(
                 r#"^(git[@|://].*)|(https://.*)|(http://.*)|(ssh://.*)$"#
             ).unwrap();
         }
+        let path = txt.trim_start_matches("file://");
+        if archive::is_archive(path) && (path != txt || DIRECTORY.is_match(txt)) {
+            return Ok(Url::Archive(path.to_owned()));
+        }
+        if DIRECTORY.is_match(txt) {
+            return Ok(Url::Directory(txt.to_owned()));
+        }
         LOCAL
             .captures(txt)
             .map(|caps| Url::Local(caps.get(1).unwrap().as_str().to_owned()))
@@ -33,6 +72,14 @@ impl Url {
             } else {
                 None
             })
+            .or_else(|| {
+                SHORTHAND.captures(txt).map(|caps| {
+                    Url::Shorthand(
+                        caps.get(1).unwrap().as_str().to_owned(),
+                        caps.get(2).unwrap().as_str().to_owned(),
+                    )
+                })
+            })
             .or_else(|| {
                 GH.captures(txt).map(|caps| {
                     Url::Github(
@@ -43,64 +90,457 @@ impl Url {
             })
             .ok_or(ErrorKind::InvalidUri(txt.into()).into())
     }
+
+    /// the url git clones this repository from. host aliases are looked
+    /// up in a table of user defined `hosts` before built in ones
+    pub fn clone_url(&self, hosts: &BTreeMap<String, String>) -> Result<String> {
+        match *self {
+            Url::Github(ref owner, ref repo) => {
+                Ok(format!("https://github.com/{}/{}.git", owner, repo))
+            }
+            Url::Shorthand(ref alias, ref path) => {
+                let base = hosts
+                    .get(alias)
+                    .map(String::as_str)
+                    .or_else(|| {
+                        HOSTS.iter().find(|h| h.0 == alias).map(|h| h.1)
+                    })
+                    .ok_or_else(|| {
+                        format!(
+                            "unknown host alias {}. define it in the [hosts] table of {}",
+                            alias,
+                            ::config::file()
+                                .map(|file| file.display().to_string())
+                                .unwrap_or_else(|_| "your config.toml".to_owned())
+                        )
+                    })?;
+                let path = path.trim_end_matches(".git");
+                // scp like ssh urls, git@host:, are joined without a separator
+                Ok(if base.ends_with(':') || base.ends_with('/') {
+                    format!("{}{}.git", base, path)
+                } else {
+                    format!("{}/{}.git", base, path)
+                })
+            }
+            Url::Directory(ref path) |
+            Url::Archive(ref path) |
+            Url::Local(ref path) => Ok(path.to_owned()),
+            Url::Remote(ref remote) => Ok(remote.to_owned()),
+        }
+    }
+}
+
+/// A template reference, a repository url optionally followed by
+/// a `//subdirectory` within it and, for git repositories, an `@revision`,
+/// such as `user/repo//templates/rust-lib@v2`
+#[derive(Debug, PartialEq, Clone)]
+pub struct Reference {
+    pub url: Url,
+    /// directory within the repository to use as the template root
+    pub base: Option<String>,
+    pub rev: Option<String>,
+}
+
+impl Reference {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(txt: &str) -> Result<Reference> {
+        lazy_static! {
+            // a url's scheme and user, whose separators are not part of
+            // a subdirectory or revision
+            static ref PREFIX: Regex =
+                Regex::new(r#"^([A-Za-z][\w+.-]*://)?([^@/:\s]+@)?"#).unwrap();
+        }
+        let prefix = PREFIX.find(txt).map_or(0, |m| m.end());
+        let (mut url, mut base) = match txt[prefix..].find("//") {
+            Some(slashes) => (&txt[..prefix + slashes], Some(&txt[prefix + slashes + 2..])),
+            None => (txt, None),
+        };
+        // revisions follow the repository or its subdirectory. local
+        // directories and archives have none, and may have `@` in their paths
+        let local = matches!(
+            Url::from_str(txt),
+            Ok(Url::Directory(_)) | Ok(Url::Archive(_))
+        );
+        let mut rev = None;
+        if !local {
+            match base {
+                Some(path) => {
+                    if let Some(at) = path.find('@') {
+                        base = Some(&path[..at]);
+                        rev = Some(&path[at + 1..]);
+                    }
+                }
+                None => {
+                    if let Some(at) = url[prefix..].find('@') {
+                        rev = Some(&url[prefix + at + 1..]);
+                        url = &url[..prefix + at];
+                    }
+                }
+            }
+        }
+        let base = base.map(|base| base.trim_matches('/'));
+        let nonempty = |s: Option<&str>| {
+            s.filter(|s| !s.is_empty()).map(str::to_owned)
+        };
+        Ok(Reference {
+            url: Url::from_str(url)?,
+            base: nonempty(base),
+            rev: nonempty(rev),
+        })
+    }
 }
 
-/// clone a repository at a rev to a directory
+impl fmt::Display for Url {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Url::Directory(ref path) | Url::Archive(ref path) => write!(f, "{}", path),
+            Url::Local(ref path) => write!(f, "file://{}", path),
+            Url::Github(ref owner, ref repo) => write!(f, "{}/{}", owner, repo),
+            Url::Shorthand(ref alias, ref path) => write!(f, "{}:{}", alias, path),
+            Url::Remote(ref remote) => write!(f, "{}", remote),
+        }
+    }
+}
+
+/// directory cached template repositories are kept in,
+/// `$XDG_CACHE_HOME/porteurbars` falling back on `~/.cache/porteurbars`,
+/// or the system's temporary directory where there's no home directory
+pub fn cache_dir() -> PathBuf {
+    env::var_os("XDG_CACHE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+        .unwrap_or_else(env::temp_dir)
+        .join("porteurbars")
+}
+
+/// name of the directory a repository is cached in, its clone url with
+/// bytes other than ascii alphanumerics, `.` and `-` percent-escaped so
+/// that different urls never share a cache
+fn cache_key(url: &str) -> String {
+    url.bytes()
+        .map(|b| if b.is_ascii_alphanumeric() || b == b'.' || b == b'-' {
+            (b as char).to_string()
+        } else {
+            format!("%{:02X}", b)
+        })
+        .collect()
+}
+
+/// clone a repository at a rev, or its default branch when none is
+/// given, to a directory, returning the id of the commit checked out.
+/// see `resolve` for supported revisions.
+/// repositories are cached, fetching only what's changed since
+/// they were last cloned. when offline, the cached copy is used
+/// without fetching. when a base directory is given only its
+/// contents are checked out.
 /// a best attempt effort is made to authenticate
 /// requests when required to support private
 /// git repositories
-pub fn clone<P, R>(repo: Url, dir: P, rev: R) -> Result<()>
+pub fn clone<P>(
+    repo: Url,
+    dir: P,
+    rev: Option<&str>,
+    base: Option<&str>,
+    offline: bool,
+) -> Result<String>
+where
+    P: AsRef<Path>,
+{
+    clone_with_cache(repo, &Config::load()?, &cache_dir(), dir, rev, base, offline)
+}
+
+fn clone_with_cache<P>(
+    repo: Url,
+    config: &Config,
+    cache: &Path,
+    dir: P,
+    rev: Option<&str>,
+    base: Option<&str>,
+    offline: bool,
+) -> Result<String>
 where
     P: AsRef<Path>,
-    R: Into<String>,
 {
-    let mut cb = git2::RemoteCallbacks::new();
-    let mut tried_sshkey = false;
-    cb.credentials(move |url, username, cred_type| {
-        if cred_type.contains(git2::CredentialType::USER_PASS_PLAINTEXT) {
-            let cfg = git2::Config::open_default().unwrap();
-            return git2::Cred::credential_helper(&cfg, url, username);
-        }
-        if cred_type.contains(git2::CredentialType::SSH_KEY) && !tried_sshkey {
-            // If ssh-agent authentication fails, libgit2 will keep
-            // calling this callback asking for other authentication
-            // methods to try. Make sure we only try ssh-agent once,
-            // to avoid looping forever.
-            tried_sshkey = true;
-            let username = username.unwrap();
-            return git2::Cred::ssh_key_from_agent(&username);
-        }
-        Err(git2::Error::from_str("no authentication available"))
-    });
+    let url = &repo.clone_url(&config.hosts)?;
+    let key = cache_key(url);
+    let cached = cache.join(&key);
+    // concurrent runs wait for each other's use of a cached copy. the lock
+    // is released when its file is closed
+    fs::create_dir_all(cache)?;
+    let lock = File::create(cache.join(format!("{}.lock", key)))?;
+    lock.lock_exclusive().chain_err(|| {
+        format!("failed to lock cached copy of {}", url)
+    })?;
+    if offline {
+        if !cached.exists() {
+            bail!(
+                "template {} has not been cached. run once without --offline to cache it",
+                repo
+            );
+        }
+    } else {
+        // libgit2 does not support shallow fetches of local repositories
+        let shallow =
+            !matches!(repo, Url::Directory(_) | Url::Archive(_) | Url::Local(_));
+        fetch(url, &cached, rev, shallow, config.ssh_key.as_deref())?;
+    }
+
+    // the cached copy's HEAD tracks the remote's default branch
+    let revision = rev.unwrap_or("HEAD");
+    let repository = git2::Repository::open_bare(&cached)?;
+    let commit = resolve(&repository, revision).chain_err(|| {
+        format!("failed to resolve revision {} of repo {}", revision, url)
+    })?;
+    fs::create_dir_all(dir.as_ref())?;
+    let mut checkout = CheckoutBuilder::new();
+    checkout.target_dir(dir.as_ref()).force();
+    if let Some(base) = base {
+        checkout.path(format!("{}/", base.trim_matches('/')));
+    }
+    repository.checkout_tree(
+        repository.find_commit(commit)?.as_object(),
+        Some(&mut checkout),
+    )?;
+
+    debug!("cloned {:?} at {} to {:?}", repo, commit, dir.as_ref());
+    Ok(commit.to_string())
+}
+
+/// resolves a revision to a commit. revisions may be any git revspec,
+/// such as a branch, tag or full or abbreviated commit id, or a semver
+/// requirement matched against `v` prefixed version tags
+fn resolve(repository: &git2::Repository, rev: &str) -> Result<git2::Oid> {
+    if let Ok(object) = repository.revparse_single(rev) {
+        return Ok(object.peel_to_commit()?.id());
+    }
+    let req = match VersionReq::parse(rev) {
+        Ok(req) => req,
+        Err(_) => {
+            let branches = repository
+                .branches(Some(git2::BranchType::Local))?
+                .filter_map(|branch| branch.ok())
+                .filter_map(|(branch, _)| {
+                    branch.name().ok().and_then(|n| n.map(str::to_owned))
+                })
+                .collect::<Vec<_>>();
+            bail!(
+                "{} is not a known revision or version requirement. available branches: {}",
+                rev,
+                branches.join(", ")
+            )
+        }
+    };
+    let tags = repository.tag_names(Some("v*"))?;
+    match latest(&req, tags.iter().flatten()) {
+        Some(tag) => {
+            debug!("resolved {} to tag {}", rev, tag);
+            let object = repository.revparse_single(&format!("refs/tags/{}", tag))?;
+            Ok(object.peel_to_commit()?.id())
+        }
+        None => bail!("no version tags match {}", req),
+    }
+}
+
+/// the greatest of a set of `v` prefixed version tags matching a requirement
+fn latest<'a, I>(req: &VersionReq, tags: I) -> Option<&'a str>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    tags.into_iter()
+        .filter(|tag| tag.starts_with('v'))
+        .filter_map(|tag| {
+            Version::parse(&tag[1..]).ok().map(|version| (version, tag))
+        })
+        .filter(|(version, _)| req.matches(version))
+        .max_by(|a, b| a.0.cmp(&b.0))
+        .map(|(_, tag)| tag)
+}
 
+/// fetch what's needed to resolve a revision into a bare cached copy of
+/// a repository, creating it if needed.
+/// branches, tags and version requirements matching the refs a remote
+/// advertises fetch only that ref, shallowly when `shallow`. other
+/// revisions, such as commits, fetch all branches unless already cached.
+/// tags are only fetched when requested
+fn fetch(
+    url: &str,
+    cached: &Path,
+    rev: Option<&str>,
+    shallow: bool,
+    ssh_key: Option<&Path>,
+) -> Result<()> {
+    let repository = match git2::Repository::open_bare(cached) {
+        Ok(repository) => repository,
+        Err(_) => {
+            fs::create_dir_all(cached)?;
+            git2::Repository::init_bare(cached)?
+        }
+    };
+    // git asks for credentials afresh on each connection, so each gets its
+    // own record of the methods tried
+    let credentials = || Credentials::new(ssh_key.map(Path::to_path_buf));
+    let failed = |credentials: &Credentials, e: git2::Error, action: &str| -> Error {
+        let tried = credentials.tried();
+        let rejected = credentials.exhausted() || e.code() == git2::ErrorCode::Auth;
+        if rejected && !tried.is_empty() {
+            Error::with_chain(
+                e,
+                ErrorKind::Unauthenticated(url.to_owned(), tried.join(", ")),
+            )
+        } else {
+            Error::with_chain(e, format!("failed to {} repo {}", action, url))
+        }
+    };
+    let mut remote = repository.remote_anonymous(url)?;
+    let connecting = credentials();
+    let mut connection = remote
+        .connect_auth(git2::Direction::Fetch, Some(connecting.callbacks()), None)
+        .map_err(|e| failed(&connecting, e, "connect to"))?;
+    let refs = connection
+        .list()?
+        .iter()
+        .map(|head| head.name().to_owned())
+        .collect::<Vec<_>>();
+    let head = default_branch(connection.list()?);
+
+    let has = |name: &String| refs.contains(name);
+    let wanted = match rev {
+        None => head.clone(),
+        Some(rev) => {
+            let branch = format!("refs/heads/{}", rev);
+            let tag = format!("refs/tags/{}", rev);
+            if has(&branch) {
+                Some(branch)
+            } else if has(&tag) {
+                Some(tag)
+            } else {
+                VersionReq::parse(rev).ok().and_then(|req| {
+                    latest(
+                        &req,
+                        refs.iter().filter_map(|r| r.strip_prefix("refs/tags/")),
+                    ).map(|tag| format!("refs/tags/{}", tag))
+                })
+            }
+        }
+    };
+    let fetching = credentials();
     let mut fo = git2::FetchOptions::new();
-    fo.remote_callbacks(cb).download_tags(
-        git2::AutotagOption::All,
-    );
-    let url = match repo {
-        Url::Github(ref owner, ref repo) => {
-            format!("git://github.com/{}/{}.git", owner, repo)
-        }
-        Url::Local(ref path) => path.to_owned(),
-        Url::Remote(ref remote) => remote.to_owned(),
+    fo.remote_callbacks(fetching.callbacks())
+        .download_tags(git2::AutotagOption::None);
+    let refspecs = match wanted {
+        Some(ref name) => {
+            if shallow {
+                fo.depth(1);
+            }
+            vec![format!("+{}:{}", name, name)]
+        }
+        None => {
+            let cached_commit = rev.is_some_and(|rev| {
+                git2::Oid::from_str(rev).is_ok() &&
+                    repository.revparse_single(rev).is_ok()
+            });
+            if cached_commit {
+                Vec::new()
+            } else {
+                if repository.is_shallow() {
+                    // GIT_FETCH_DEPTH_UNSHALLOW
+                    fo.depth(i32::MAX);
+                }
+                // commits may only be reachable from tags, such as
+                // releases cut from since deleted branches
+                vec![
+                    "+refs/heads/*:refs/heads/*".to_owned(),
+                    "+refs/tags/*:refs/tags/*".to_owned(),
+                ]
+            }
+        }
     };
-    let revision = rev.into();
-    RepoBuilder::new()
-        .branch(&revision)
-        .fetch_options(fo)
-        .clone(&url, dir.as_ref())
-        .chain_err(|| {
-            format!("failed to clone repo {}@{}", &url, revision.clone())
-        })?;
-
-    debug!("cloned {:?} to {:?}", repo, dir.as_ref());
+    if !refspecs.is_empty() {
+        connection
+            .remote()
+            .fetch(&refspecs, Some(&mut fo), None)
+            .map_err(|e| failed(&fetching, e, "fetch"))?;
+    }
+    if let Some(head) = head {
+        repository.set_head(&head)?;
+    }
+    debug!("fetched {:?} of {} into {:?}", refspecs, url, cached);
     Ok(())
 }
 
+/// the branch a remote's HEAD refers to. remotes which don't advertise
+/// it as a symbolic ref are matched on the commit their HEAD points to
+fn default_branch(heads: &[git2::RemoteHead]) -> Option<String> {
+    let head = heads.iter().find(|head| head.name() == "HEAD")?;
+    if let Some(target) = head.symref_target() {
+        return Some(target.to_owned());
+    }
+    heads
+        .iter()
+        .find(|branch| {
+            branch.name().starts_with("refs/heads/") && branch.oid() == head.oid()
+        })
+        .map(|branch| branch.name().to_owned())
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use tempdir::TempDir;
+
+    /// commits a file, at the root of a repository and within a base
+    /// directory, to its current branch
+    fn commit(repo: &git2::Repository, content: &str) -> git2::Oid {
+        let workdir = repo.workdir().unwrap();
+        fs::create_dir_all(workdir.join("base")).unwrap();
+        let mut index = repo.index().unwrap();
+        for file in &["file", "base/file"] {
+            fs::write(workdir.join(file), content).unwrap();
+            index.add_path(Path::new(file)).unwrap();
+        }
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let sig = git2::Signature::now("porteurbars", "p@example.com").unwrap();
+        let parents = repo.head()
+            .ok()
+            .and_then(|head| head.peel_to_commit().ok())
+            .into_iter()
+            .collect::<Vec<_>>();
+        let parents = parents.iter().collect::<Vec<_>>();
+        repo.commit(Some("HEAD"), &sig, &sig, content, &tree, &parents)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_clone_resolves_revisions() {
+        let origin = TempDir::new("porteurbars-origin").unwrap();
+        let repo = git2::Repository::init(origin.path()).unwrap();
+        let mut first = None;
+        for version in &["1.0.0", "1.2.0", "2.0.0"] {
+            let id = commit(&repo, version);
+            first = first.or(Some(id));
+            let object = repo.find_object(id, None).unwrap();
+            repo.tag_lightweight(&format!("v{}", version), &object, false)
+                .unwrap();
+        }
+        let first = first.unwrap();
+        let cache = TempDir::new("porteurbars-cache").unwrap();
+        let path = origin.path().to_string_lossy().into_owned();
+        let url = Url::Local(path.clone());
+        let config = Config::default();
+        let checkout = |rev: &str| {
+            let dir = TempDir::new("porteurbars-checkout").unwrap();
+            clone_with_cache(url.clone(), &config, cache.path(), dir.path(), Some(rev), None, false)
+                .map(|_| fs::read_to_string(dir.path().join("file")).unwrap())
+        };
+        assert_eq!(checkout("master").unwrap(), "2.0.0");
+        assert_eq!(checkout("v1.2.0").unwrap(), "1.2.0");
+        assert_eq!(checkout(&first.to_string()[..7]).unwrap(), "1.0.0");
+        assert_eq!(checkout("^1").unwrap(), "1.2.0");
+        assert_eq!(checkout("~1.0").unwrap(), "1.0.0");
+        assert!(checkout("^3").is_err());
+        assert!(checkout("missing-branch").is_err());
+    }
 
     #[test]
     fn test_authenticated_ssh_url() {
@@ -134,6 +574,108 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_shorthand_url() {
+        assert_eq!(
+            Url::from_str("gl:group/sub/repo").unwrap(),
+            Url::Shorthand(String::from("gl"), String::from("group/sub/repo"))
+        );
+        assert!(Url::from_str("gl:").is_err());
+    }
+
+    #[test]
+    fn test_clone_url() {
+        let mut hosts = BTreeMap::new();
+        hosts.insert("work".to_owned(), "https://git.example.com/".to_owned());
+        hosts.insert("ssh".to_owned(), "git@git.example.com:".to_owned());
+        let clone_url = |uri: &str| Url::from_str(uri).unwrap().clone_url(&hosts);
+        assert_eq!(
+            clone_url("user/repo").unwrap(),
+            "https://github.com/user/repo.git"
+        );
+        assert_eq!(
+            clone_url("gh:user/repo").unwrap(),
+            "https://github.com/user/repo.git"
+        );
+        assert_eq!(
+            clone_url("gl:group/sub/repo").unwrap(),
+            "https://gitlab.com/group/sub/repo.git"
+        );
+        assert_eq!(
+            clone_url("bb:user/repo.git").unwrap(),
+            "https://bitbucket.org/user/repo.git"
+        );
+        assert_eq!(
+            clone_url("work:team/repo").unwrap(),
+            "https://git.example.com/team/repo.git"
+        );
+        assert_eq!(
+            clone_url("ssh:team/repo").unwrap(),
+            "git@git.example.com:team/repo.git"
+        );
+        assert!(clone_url("nope:team/repo").is_err());
+    }
+
+    #[test]
+    fn test_reference() {
+        let reference = |txt: &str| {
+            let r = Reference::from_str(txt).unwrap();
+            (r.url.to_string(), r.base, r.rev)
+        };
+        let some = |s: &str| Some(s.to_owned());
+        assert_eq!(
+            reference("user/repo//templates/rust-lib@v2"),
+            ("user/repo".to_owned(), some("templates/rust-lib"), some("v2"))
+        );
+        assert_eq!(
+            reference("user/repo@feature/x"),
+            ("user/repo".to_owned(), None, some("feature/x"))
+        );
+        assert_eq!(
+            reference("gl:group/repo//rust"),
+            ("gl:group/repo".to_owned(), some("rust"), None)
+        );
+        assert_eq!(
+            reference("git@github.com:user/repo.git//rust@^1"),
+            ("git@github.com:user/repo.git".to_owned(), some("rust"), some("^1"))
+        );
+        assert_eq!(
+            reference("ssh://git@host.com/user/repo.git"),
+            ("ssh://git@host.com/user/repo.git".to_owned(), None, None)
+        );
+        assert_eq!(
+            reference("file:///path/to/repo//rust/"),
+            ("file:///path/to/repo".to_owned(), some("rust"), None)
+        );
+        assert_eq!(
+            reference("file:///path/to/my@repo//rust@v1"),
+            ("file:///path/to/my@repo".to_owned(), some("rust"), some("v1"))
+        );
+        assert_eq!(reference("./my@tpl"), ("./my@tpl".to_owned(), None, None));
+        assert_eq!(
+            reference("./templates@2//rust"),
+            ("./templates@2".to_owned(), some("rust"), None)
+        );
+        assert_eq!(
+            reference("/tmp/tpl@v1.zip"),
+            ("/tmp/tpl@v1.zip".to_owned(), None, None)
+        );
+    }
+
+    #[test]
+    fn test_display_round_trips() {
+        let uris = [
+            "user/repo",
+            "file:///some/path",
+            "git@github.com:u/r.git",
+            "./some/path",
+            "gl:group/repo",
+        ];
+        for uri in &uris {
+            assert_eq!(Url::from_str(uri).unwrap().to_string(), *uri)
+        }
+    }
+
     #[test]
     fn test_local_uri() {
         assert_eq!(
@@ -141,4 +683,184 @@ mod tests {
             Url::Local(String::from("/some/path/foo.git"))
         )
     }
+
+    #[test]
+    fn test_archive() {
+        for uri in &["./tpl.tar.gz", "/tpl.tgz", "file:///tpl.zip"] {
+            assert_eq!(
+                Url::from_str(uri).unwrap(),
+                Url::Archive(uri.trim_start_matches("file://").to_owned())
+            )
+        }
+    }
+
+    #[test]
+    fn test_directory() {
+        for path in &[".", "..", "./tpl", "../tpl", "/abs/tpl", "C:\\tpl"] {
+            assert_eq!(
+                Url::from_str(path).unwrap(),
+                Url::Directory(String::from(*path))
+            )
+        }
+    }
+
+    #[test]
+    fn test_cache_key() {
+        assert_eq!(
+            cache_key("git@github.com:user/repo.git"),
+            "git%40github.com%3Auser%2Frepo.git"
+        );
+        let key = |uri: &str| {
+            cache_key(&Url::from_str(uri).unwrap().clone_url(&BTreeMap::new()).unwrap())
+        };
+        assert_ne!(key("gh_user/repo"), key("gh:user/repo"));
+        assert_ne!(key("https://host/a_b.git"), key("https://host/a/b.git"));
+        assert_eq!(key("user/repo"), key("gh:user/repo"));
+    }
+
+    #[test]
+    fn test_fetch_commits_only_reachable_from_tags() {
+        let origin = TempDir::new("porteurbars-origin").unwrap();
+        let repo = git2::Repository::init(origin.path()).unwrap();
+        let first = commit(&repo, "1.0.0");
+        let tagged = commit(&repo, "2.0.0");
+        repo.tag_lightweight("v2.0.0", &repo.find_object(tagged, None).unwrap(), false)
+            .unwrap();
+        repo.reference("refs/heads/master", first, true, "drop release")
+            .unwrap();
+        let cache = TempDir::new("porteurbars-cache").unwrap();
+        let path = origin.path().to_string_lossy().into_owned();
+        let cached = cache.path().join(cache_key(&path));
+        fetch(&path, &cached, Some(&tagged.to_string()), true, None).unwrap();
+        let cached = git2::Repository::open_bare(&cached).unwrap();
+        assert!(cached.find_commit(tagged).is_ok());
+        assert!(cached.find_reference("refs/tags/v2.0.0").is_ok());
+    }
+
+    #[test]
+    fn test_fetch_reports_rejected_credentials() {
+        use std::io::{Read, Write};
+        use std::net::TcpListener;
+        // a server demanding credentials it never accepts
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/repo.git", listener.local_addr().unwrap());
+        ::std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut request = [0; 4096];
+                let _ = stream.read(&mut request);
+                let _ = stream.write_all(
+                    b"HTTP/1.1 401 Unauthorized\r\n\
+                      WWW-Authenticate: Basic realm=\"porteurbars\"\r\n\
+                      Content-Length: 0\r\n\
+                      Connection: close\r\n\r\n",
+                );
+            }
+        });
+        let cache = TempDir::new("porteurbars-cache").unwrap();
+        let err = fetch(&url, &cache.path().join("repo"), None, true, None)
+            .unwrap_err();
+        match *err.kind() {
+            ErrorKind::Unauthenticated(ref rejected, ref tried) => {
+                assert_eq!(rejected, &url);
+                assert!(tried.ends_with("git credential helper"));
+            }
+            ref kind => panic!("expected authentication failure, got {}", kind),
+        }
+        assert!(
+            err.to_string()
+                .starts_with(&format!("failed to authenticate with {}. tried ", url))
+        );
+    }
+
+    #[test]
+    fn test_clone_defaults_to_remote_head() {
+        let origin = TempDir::new("porteurbars-origin").unwrap();
+        let repo = git2::Repository::init(origin.path()).unwrap();
+        repo.set_head("refs/heads/main").unwrap();
+        commit(&repo, "main");
+        let cache = TempDir::new("porteurbars-cache").unwrap();
+        let path = origin.path().to_string_lossy().into_owned();
+        let url = Url::Local(path.clone());
+        let config = Config::default();
+        let dir = TempDir::new("porteurbars-checkout").unwrap();
+        clone_with_cache(url.clone(), &config, cache.path(), dir.path(), None, None, false)
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.path().join("file")).unwrap(),
+            "main"
+        );
+        let err = clone_with_cache(
+            url,
+            &config,
+            cache.path(),
+            dir.path(),
+            Some("master"),
+            None,
+            false,
+        ).unwrap_err();
+        assert!(
+            err.iter()
+                .any(|e| e.to_string().ends_with("available branches: main"))
+        );
+    }
+
+    #[test]
+    fn test_concurrent_clones_share_cache() {
+        let origin = TempDir::new("porteurbars-origin").unwrap();
+        let repo = git2::Repository::init(origin.path()).unwrap();
+        commit(&repo, "shared");
+        let cache = TempDir::new("porteurbars-cache").unwrap();
+        let path = origin.path().to_string_lossy().into_owned();
+        let clones = (0..4)
+            .map(|_| {
+                let (path, cache) = (path.clone(), cache.path().to_path_buf());
+                ::std::thread::spawn(move || {
+                    let dir = TempDir::new("porteurbars-checkout").unwrap();
+                    clone_with_cache(
+                        Url::Local(path.clone()),
+                        &Config::default(),
+                        &cache,
+                        dir.path(),
+                        None,
+                        None,
+                        false,
+                    ).unwrap();
+                    fs::read_to_string(dir.path().join("file")).unwrap()
+                })
+            })
+            .collect::<Vec<_>>();
+        for clone in clones {
+            assert_eq!(clone.join().unwrap(), "shared");
+        }
+    }
+
+    #[test]
+    fn test_clone_fetches_only_what_is_needed() {
+        let origin = TempDir::new("porteurbars-origin").unwrap();
+        let repo = git2::Repository::init(origin.path()).unwrap();
+        let id = commit(&repo, "1.0.0");
+        repo.tag_lightweight("v1.0.0", &repo.find_object(id, None).unwrap(), false)
+            .unwrap();
+        let cache = TempDir::new("porteurbars-cache").unwrap();
+        let path = origin.path().to_string_lossy().into_owned();
+        let url = Url::Local(path.clone());
+        let config = Config::default();
+        let dir = TempDir::new("porteurbars-checkout").unwrap();
+        clone_with_cache(
+            url.clone(),
+            &config,
+            cache.path(),
+            dir.path(),
+            None,
+            Some("base"),
+            false,
+        ).unwrap();
+        assert!(dir.path().join("base/file").exists());
+        assert!(!dir.path().join("file").exists());
+        let cached =
+            git2::Repository::open_bare(cache.path().join(cache_key(&path)))
+                .unwrap();
+        assert_eq!(cached.tag_names(None).unwrap().len(), 0);
+    }
 }
@@ -0,0 +1,142 @@
+//! Handlebars helpers a template declares in a helpers file
+
+use errors::{Result, ResultExt};
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use toml;
+
+/// name of the file declaring a template's helpers
+pub const FILE: &str = "helpers.toml";
+
+#[derive(Debug, Default, Deserialize)]
+struct Helpers {
+    #[serde(default, rename = "helper")]
+    helpers: Vec<Declaration>,
+}
+
+/// A helper as declared in a helpers file
+#[derive(Debug, Deserialize)]
+struct Declaration {
+    name: String,
+    #[serde(default)]
+    replace: Vec<Replacement>,
+    #[serde(default)]
+    map: BTreeMap<String, String>,
+    #[serde(default)]
+    default: Option<String>,
+}
+
+/// A regex replacement rule. replacements may refer to captured
+/// groups as `$1` or `${name}`
+#[derive(Debug, Deserialize)]
+struct Replacement {
+    pattern: String,
+    with: String,
+}
+
+/// A helper transforming a value by first applying each of its regex
+/// replacements in order, then looking the result up in its map.
+/// values missing from a map resolve to its default, if any, or are
+/// left as they are
+#[derive(Debug)]
+pub struct Helper {
+    pub name: String,
+    replace: Vec<(Regex, String)>,
+    map: BTreeMap<String, String>,
+    default: Option<String>,
+}
+
+impl Helper {
+    pub fn apply(&self, value: &str) -> String {
+        let replaced = self.replace.iter().fold(
+            value.to_owned(),
+            |value, (pattern, with)| pattern.replace_all(&value, with.as_str()).into_owned(),
+        );
+        if self.map.is_empty() {
+            return replaced;
+        }
+        self.map
+            .get(&replaced)
+            .or(self.default.as_ref())
+            .cloned()
+            .unwrap_or(replaced)
+    }
+}
+
+/// parses helpers from a target file
+pub fn from_file<P>(path: P) -> Result<Vec<Helper>>
+where
+    P: AsRef<Path>,
+{
+    from_string(&fs::read_to_string(path)?)
+}
+
+/// parses helpers from a string of toml
+pub fn from_string(s: &str) -> Result<Vec<Helper>> {
+    let Helpers { helpers } = toml::from_str(s)?;
+    helpers
+        .into_iter()
+        .map(|declaration| {
+            let name = declaration.name;
+            let replace = declaration
+                .replace
+                .into_iter()
+                .map(|Replacement { pattern, with }| {
+                    let regex = Regex::new(&pattern).chain_err(|| {
+                        format!("invalid pattern {} in helper {}", pattern, name)
+                    })?;
+                    Ok((regex, with))
+                })
+                .collect::<Result<_>>()?;
+            Ok(Helper {
+                name,
+                replace,
+                map: declaration.map,
+                default: declaration.default,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_string() {
+        let helpers = from_string(
+            r#"
+[[helper]]
+name = "crate_name"
+replace = [
+  { pattern = "[^A-Za-z0-9]+", with = "_" },
+  { pattern = "^(\\d)", with = "_$1" },
+]
+
+[[helper]]
+name = "license_url"
+default = "https://opensource.org/licenses"
+[helper.map]
+MIT = "https://opensource.org/licenses/MIT"
+"#,
+        ).unwrap();
+        assert_eq!(helpers[0].name, "crate_name");
+        assert_eq!(helpers[0].apply("my-cool.app"), "my_cool_app");
+        assert_eq!(helpers[0].apply("3d"), "_3d");
+        assert_eq!(
+            helpers[1].apply("MIT"),
+            "https://opensource.org/licenses/MIT"
+        );
+        assert_eq!(helpers[1].apply("WTFPL"), "https://opensource.org/licenses");
+    }
+
+    #[test]
+    fn test_invalid_pattern() {
+        let err = from_string(
+            "[[helper]]\nname = \"bad\"\nreplace = [{ pattern = \"(\", with = \"\" }]\n",
+        ).unwrap_err();
+        assert_eq!(err.to_string(), "invalid pattern ( in helper bad");
+    }
+}
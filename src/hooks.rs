@@ -0,0 +1,65 @@
+use errors::{ErrorKind, Result, ResultExt};
+use manifest::Hooks;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::Command;
+
+/// asks the user whether a template's hooks may be run.
+/// templates come from arbitrary remotes so hooks are never run
+/// without explicit consent
+pub fn confirm(hooks: &Hooks) -> io::Result<bool> {
+    println!("\nThis template would like to run the following commands\n");
+    for command in &hooks.pre {
+        println!("  before rendering: {}", command);
+    }
+    for command in &hooks.post {
+        println!("  after rendering: {}", command);
+    }
+    let mut answer = String::new();
+    print!("\nType `y` to run them: ");
+    io::stdout().flush()?;
+    io::stdin().read_line(&mut answer)?;
+    Ok(answer.trim().to_lowercase() == "y")
+}
+
+/// runs a list of commands in a directory, exporting the
+/// template's context as environment variables
+pub fn run<P>(
+    commands: &[String],
+    dir: P,
+    ctx: &BTreeMap<String, String>,
+) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    for command in commands {
+        debug!("running hook {}", command);
+        let status = shell(command)
+            .current_dir(dir.as_ref())
+            .envs(ctx)
+            .status()
+            .chain_err(|| format!("failed to run hook {}", command))?;
+        if !status.success() {
+            return Err(
+                ErrorKind::HookFailed(command.clone(), status.to_string())
+                    .into(),
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    let mut cmd = Command::new("cmd");
+    cmd.arg("/C").arg(command);
+    cmd
+}
+
+#[cfg(not(windows))]
+fn shell(command: &str) -> Command {
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c").arg(command);
+    cmd
+}
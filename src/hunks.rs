@@ -0,0 +1,107 @@
+//! Hunks are contiguous changes between the current and new content
+//! of a file which may be individually accepted or rejected
+
+use difference::{Changeset, Difference};
+
+/// A segment of content, either shared by both versions of a file
+/// or a hunk of lines which differ between them
+#[derive(Debug, PartialEq)]
+pub enum Segment {
+    Same(String),
+    Hunk {
+        current: Option<String>,
+        new: Option<String>,
+    },
+}
+
+/// splits current and new content into segments of shared lines and hunks
+pub fn segments(current: &str, new: &str) -> Vec<Segment> {
+    let Changeset { diffs, .. } = Changeset::new(current, new, "\n");
+    let mut segments = Vec::new();
+    for difference in diffs {
+        match difference {
+            Difference::Same(lines) => segments.push(Segment::Same(lines)),
+            Difference::Rem(lines) => {
+                match segments.last_mut() {
+                    Some(&mut Segment::Hunk { ref mut current, .. })
+                        if current.is_none() => *current = Some(lines),
+                    _ => {
+                        segments.push(Segment::Hunk {
+                            current: Some(lines),
+                            new: None,
+                        })
+                    }
+                }
+            }
+            Difference::Add(lines) => {
+                match segments.last_mut() {
+                    Some(&mut Segment::Hunk { ref mut new, .. })
+                        if new.is_none() => *new = Some(lines),
+                    _ => {
+                        segments.push(Segment::Hunk {
+                            current: None,
+                            new: Some(lines),
+                        })
+                    }
+                }
+            }
+        }
+    }
+    segments
+}
+
+/// joins segments back into content, using the lines chosen for each hunk
+/// in order. a hunk with no chosen lines contributes nothing
+pub fn join(segments: &[Segment], choices: &[Option<String>]) -> String {
+    let mut choices = choices.iter();
+    let mut lines = Vec::new();
+    for segment in segments {
+        match *segment {
+            Segment::Same(ref same) => lines.push(same.as_str()),
+            Segment::Hunk { .. } => {
+                if let Some(Some(chosen)) = choices.next() {
+                    lines.push(chosen.as_str())
+                }
+            }
+        }
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn segments_group_changes_into_hunks() {
+        assert_eq!(
+            segments("a\nb\nc\n", "a\nB\nc\nd\n"),
+            vec![
+                Segment::Same("a".to_owned()),
+                Segment::Hunk {
+                    current: Some("b".to_owned()),
+                    new: Some("B".to_owned()),
+                },
+                Segment::Same("c".to_owned()),
+                Segment::Hunk {
+                    current: None,
+                    new: Some("d".to_owned()),
+                },
+                Segment::Same("".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn join_applies_choices() {
+        let current = "a\nb\nc\n";
+        let new = "a\nB\nc\nd\n";
+        let segments = segments(current, new);
+        let current_choices = vec![Some("b".to_owned()), None];
+        assert_eq!(join(&segments, &current_choices), current);
+        let new_choices = vec![Some("B".to_owned()), Some("d".to_owned())];
+        assert_eq!(join(&segments, &new_choices), new);
+        let mixed = vec![Some("b".to_owned()), Some("d".to_owned())];
+        assert_eq!(join(&segments, &mixed), "a\nb\nc\nd\n");
+    }
+}
@@ -1,24 +1,57 @@
 //! Porteurbars is a tool for sharing portable git hosted project templates
 
+#![allow(clippy::result_large_err)]
+
 extern crate case;
+extern crate chrono;
 #[macro_use]
 extern crate lazy_static;
 #[macro_use]
 extern crate log;
 extern crate difference;
+extern crate flate2;
+extern crate fs2;
+extern crate diffy;
 extern crate handlebars;
+extern crate tar;
 extern crate tempdir;
+extern crate rand;
 extern crate regex;
+extern crate semver;
 extern crate walkdir;
 extern crate git2;
+extern crate glob;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate serde_yaml;
+extern crate toml;
+extern crate uuid;
+extern crate zip;
 #[macro_use]
 extern crate error_chain;
 
+pub mod answers;
+pub mod archive;
+mod auth;
+pub mod config;
 mod defaults;
+mod helpers;
+mod hooks;
+mod hunks;
+mod manifest;
+pub mod metadata;
 pub mod git;
+pub mod source;
+
+#[cfg(test)]
+#[path = "../tests/common/mod.rs"]
+mod common;
 
 mod errors;
 pub use errors::{Error, Result, ResultExt};
 
 mod template;
-pub use template::Template;
+pub use source::TemplateSource;
+pub use template::{Applied, Change, Options, Template};
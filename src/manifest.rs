@@ -0,0 +1,314 @@
+use errors::{Result, ResultExt};
+use glob::{MatchOptions, Pattern};
+use serde::{Deserialize, Deserializer};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use toml;
+
+/// The type of value a variable accepts
+#[derive(Debug, Default, Deserialize, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    #[default]
+    String,
+    Bool,
+    Integer,
+    Choice,
+}
+
+/// A template variable declared in a manifest
+#[derive(Debug, Deserialize, PartialEq)]
+pub struct Variable {
+    pub name: String,
+    #[serde(default, rename = "type")]
+    pub kind: Kind,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default, deserialize_with = "scalar")]
+    pub default: Option<String>,
+    #[serde(default)]
+    pub required: bool,
+    #[serde(default)]
+    pub choices: Vec<String>,
+}
+
+impl Variable {
+    /// a plain string variable as declared in a default.env file
+    pub fn from_default<N, V>(name: N, value: V) -> Variable
+    where
+        N: Into<String>,
+        V: Into<String>,
+    {
+        Variable {
+            name: name.into(),
+            kind: Kind::String,
+            description: None,
+            default: Some(value.into()),
+            required: false,
+            choices: Vec::new(),
+        }
+    }
+
+    /// validates a value for this variable, returning it in normalized form
+    pub fn validate(
+        &self,
+        value: &str,
+    ) -> ::std::result::Result<String, String> {
+        if value.is_empty() {
+            if self.required {
+                return Err("a value is required".to_owned());
+            }
+            // an empty value is not one of a variable's choices, so those
+            // fall back on their default, which must be
+            match (self.kind, self.default.as_ref()) {
+                (Kind::Choice, Some(default)) if !default.is_empty() => {
+                    return self.validate(default)
+                }
+                (Kind::Choice, _) => (),
+                _ => return Ok(value.to_owned()),
+            }
+        }
+        match self.kind {
+            Kind::String => Ok(value.to_owned()),
+            Kind::Bool => {
+                match value.to_lowercase().as_ref() {
+                    "true" | "yes" | "y" => Ok("true".to_owned()),
+                    "false" | "no" | "n" => Ok("false".to_owned()),
+                    _ => Err(
+                        format!("expected true or false but got {}", value),
+                    ),
+                }
+            }
+            Kind::Integer => {
+                value.parse::<i64>().map(|i| i.to_string()).map_err(|_| {
+                    format!("expected an integer but got {}", value)
+                })
+            }
+            Kind::Choice => {
+                if self.choices.iter().any(|c| c == value) {
+                    Ok(value.to_owned())
+                } else if value.is_empty() {
+                    Err(format!("expected one of {}", self.choices.join(", ")))
+                } else {
+                    Err(format!(
+                        "expected one of {} but got {}",
+                        self.choices.join(", "),
+                        value
+                    ))
+                }
+            }
+        }
+    }
+
+    /// a short description of accepted values, if any, used in prompts
+    pub fn hint(&self) -> Option<String> {
+        match self.kind {
+            Kind::String => None,
+            Kind::Bool => Some("true/false".to_owned()),
+            Kind::Integer => Some("integer".to_owned()),
+            Kind::Choice => Some(self.choices.join("/")),
+        }
+    }
+}
+
+/// options used when matching template paths against condition globs
+pub const GLOB_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// A manifest describes the variables of a template
+#[derive(Debug, Default, Deserialize, PartialEq)]
+pub struct Manifest {
+    #[serde(default, rename = "variable")]
+    pub variables: Vec<Variable>,
+    /// globs of template paths mapped to the handlebars conditions
+    /// which must render truthy for those paths to be included
+    #[serde(default)]
+    pub conditions: BTreeMap<String, String>,
+    #[serde(default)]
+    pub hooks: Hooks,
+    /// templates applied before this one, sharing their answers with it
+    #[serde(default, rename = "include")]
+    pub includes: Vec<Include>,
+}
+
+/// A template included by another. relative directory urls are
+/// resolved against the including template
+#[derive(Debug, Deserialize, PartialEq)]
+pub struct Include {
+    pub url: String,
+    #[serde(default)]
+    pub rev: Option<String>,
+    #[serde(default)]
+    pub base: Option<String>,
+}
+
+/// Shell commands a template runs in the target directory
+/// before rendering and after files are written
+#[derive(Debug, Default, Deserialize, PartialEq)]
+pub struct Hooks {
+    #[serde(default)]
+    pub pre: Vec<String>,
+    #[serde(default)]
+    pub post: Vec<String>,
+}
+
+impl Hooks {
+    pub fn is_empty(&self) -> bool {
+        self.pre.is_empty() && self.post.is_empty()
+    }
+}
+
+impl Manifest {
+    /// compiled condition globs paired with their conditions
+    pub fn conditions(&self) -> Result<Vec<(Pattern, String)>> {
+        self.conditions
+            .iter()
+            .map(|(glob, condition)| {
+                let pattern = Pattern::new(glob).chain_err(|| {
+                    format!("invalid condition glob {}", glob)
+                })?;
+                Ok((pattern, condition.clone()))
+            })
+            .collect()
+    }
+}
+
+/// parses a manifest from a target file
+pub fn from_file<P>(path: P) -> Result<Manifest>
+where
+    P: AsRef<Path>,
+{
+    from_string(&fs::read_to_string(path)?)
+}
+
+/// parses a manifest from a string of toml
+pub fn from_string(s: &str) -> Result<Manifest> {
+    Ok(toml::from_str(s)?)
+}
+
+/// deserializes toml strings, booleans and numbers as strings
+fn scalar<'de, D>(
+    deserializer: D,
+) -> ::std::result::Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    use serde::de::Error;
+    match toml::Value::deserialize(deserializer)? {
+        toml::Value::String(s) => Ok(Some(s)),
+        toml::Value::Boolean(b) => Ok(Some(b.to_string())),
+        toml::Value::Integer(i) => Ok(Some(i.to_string())),
+        toml::Value::Float(f) => Ok(Some(f.to_string())),
+        other => Err(D::Error::custom(
+            format!("expected a string, boolean or number but got {}", other),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_string() {
+        let manifest = from_string(
+            r#"
+[[variable]]
+name = "name"
+description = "name of your project"
+required = true
+
+[[variable]]
+name = "docker"
+type = "bool"
+default = false
+
+[[variable]]
+name = "license"
+type = "choice"
+choices = ["MIT", "Apache-2.0"]
+default = "MIT"
+
+[conditions]
+"docker/**" = "{{docker}}"
+
+[hooks]
+post = ["git init"]
+
+[[include]]
+url = "gh:company/base"
+rev = "v2"
+"#,
+        ).unwrap();
+        let names = manifest
+            .variables
+            .iter()
+            .map(|v| v.name.as_ref())
+            .collect::<Vec<&str>>();
+        assert_eq!(names, vec!["name", "docker", "license"]);
+        assert_eq!(
+            manifest.variables[0].description,
+            Some("name of your project".to_owned())
+        );
+        assert!(manifest.variables[0].required);
+        assert_eq!(manifest.variables[1].kind, Kind::Bool);
+        assert_eq!(manifest.variables[1].default, Some("false".to_owned()));
+        assert_eq!(manifest.variables[2].choices, vec!["MIT", "Apache-2.0"]);
+        let conditions = manifest.conditions().unwrap();
+        assert_eq!(conditions.len(), 1);
+        assert!(conditions[0].0.matches("docker/Dockerfile"));
+        assert_eq!(conditions[0].1, "{{docker}}");
+        assert!(manifest.hooks.pre.is_empty());
+        assert_eq!(manifest.hooks.post, vec!["git init"]);
+        assert_eq!(
+            manifest.includes,
+            vec![
+                Include {
+                    url: "gh:company/base".to_owned(),
+                    rev: Some("v2".to_owned()),
+                    base: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut var = Variable::from_default("port", "8080");
+        var.kind = Kind::Integer;
+        assert_eq!(var.validate("42"), Ok("42".to_owned()));
+        assert!(var.validate("forty two").is_err());
+
+        var.kind = Kind::Bool;
+        assert_eq!(var.validate("Yes"), Ok("true".to_owned()));
+        assert!(var.validate("maybe").is_err());
+
+        var.kind = Kind::Choice;
+        var.choices = vec!["a".to_owned(), "b".to_owned()];
+        assert_eq!(var.validate("b"), Ok("b".to_owned()));
+        assert!(var.validate("c").is_err());
+
+        var.kind = Kind::String;
+        assert_eq!(var.validate(""), Ok("".to_owned()));
+        var.required = true;
+        assert!(var.validate("").is_err());
+    }
+
+    #[test]
+    fn test_validate_empty_choice() {
+        let mut var = Variable::from_default("license", "MIT");
+        var.kind = Kind::Choice;
+        var.choices = vec!["MIT".to_owned(), "Apache-2.0".to_owned()];
+        assert_eq!(var.validate(""), Ok("MIT".to_owned()));
+
+        var.default = None;
+        assert_eq!(
+            var.validate(""),
+            Err("expected one of MIT, Apache-2.0".to_owned())
+        );
+    }
+}
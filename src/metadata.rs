@@ -0,0 +1,84 @@
+//! Metadata records which template was applied to a target directory
+//! so that it may later be updated
+
+use errors::{Result, ResultExt};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use toml;
+
+/// name of file, within a target directory, recording the applied template
+pub const FILE: &str = ".porteurbars.lock";
+
+/// The source, revision and context of an applied template
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct Metadata {
+    /// uri of template repository
+    pub url: String,
+    /// revision requested when the template was applied
+    pub rev: Option<String>,
+    /// commit the requested revision resolved to
+    pub commit: Option<String>,
+    /// directory within the repository used as the template root
+    pub base: Option<String>,
+    /// values of template variables
+    #[serde(default)]
+    pub context: BTreeMap<String, String>,
+    /// commits included templates resolved to, keyed by their uri and
+    /// base directory
+    #[serde(default)]
+    pub includes: BTreeMap<String, String>,
+}
+
+impl Metadata {
+    /// reads metadata from a target directory
+    pub fn read<P>(target: P) -> Result<Metadata>
+    where
+        P: AsRef<Path>,
+    {
+        let path = target.as_ref().join(FILE);
+        let content = fs::read_to_string(&path).chain_err(|| {
+            format!("failed to read template metadata {}", path.display())
+        })?;
+        Ok(toml::from_str(&content)?)
+    }
+
+    /// writes metadata to a target directory
+    pub fn write<P>(&self, target: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let path = target.as_ref().join(FILE);
+        let content = toml::to_string(self).chain_err(
+            || "failed to serialize template metadata",
+        )?;
+        fs::write(&path, content).chain_err(|| {
+            format!("failed to write template metadata {}", path.display())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn metadata_round_trips() {
+        let dir = TempDir::new("porteurbars-test").unwrap();
+        let mut context = BTreeMap::new();
+        context.insert("name".to_owned(), "bars".to_owned());
+        let mut includes = BTreeMap::new();
+        includes.insert("https://github.com/user/base//".to_owned(), "facade".to_owned());
+        let metadata = Metadata {
+            url: "user/repo".to_owned(),
+            rev: Some("master".to_owned()),
+            commit: Some("c0ffee".to_owned()),
+            base: None,
+            context,
+            includes,
+        };
+        metadata.write(dir.path()).unwrap();
+        assert_eq!(Metadata::read(dir.path()).unwrap(), metadata);
+    }
+}
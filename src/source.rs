@@ -0,0 +1,175 @@
+//! Sources templates are fetched from
+
+use archive;
+use errors::{Result, ResultExt};
+use git::{self, Url};
+use std::fs;
+use std::path::PathBuf;
+use tempdir::TempDir;
+
+/// A local copy of a template
+#[derive(Debug)]
+pub struct Checkout {
+    /// path to template source
+    pub path: PathBuf,
+    /// id of the commit checked out, for templates fetched from git
+    pub commit: Option<String>,
+    /// url of the repository checked out, for templates fetched from git
+    pub repository: Option<Url>,
+    /// temporary directory holding the checkout, removed when dropped.
+    /// templates used in place have none
+    pub dir: Option<TempDir>,
+}
+
+/// A source of templates
+pub trait TemplateSource {
+    /// uri of the template, as recorded in the metadata of targets
+    /// it's applied to
+    fn uri(&self) -> String;
+
+    /// revision requested, as recorded in the metadata of targets it's
+    /// applied to. only templates in git have revisions
+    fn rev(&self) -> Option<String> {
+        None
+    }
+
+    /// fetches a local copy of the template
+    fn checkout(&self) -> Result<Checkout>;
+}
+
+/// A template in a git repository
+#[derive(Debug, Clone)]
+pub struct Git {
+    pub url: Url,
+    /// revision to check out, defaulting to the repository's default branch
+    pub rev: Option<String>,
+    /// directory within the repository to check out, defaulting to all of it
+    pub base: Option<String>,
+    /// when true, only previously cached copies of the repository are used
+    pub offline: bool,
+}
+
+impl Git {
+    pub fn new(url: Url) -> Git {
+        Git {
+            url,
+            rev: None,
+            base: None,
+            offline: false,
+        }
+    }
+}
+
+impl TemplateSource for Git {
+    fn uri(&self) -> String {
+        self.url.to_string()
+    }
+
+    fn rev(&self) -> Option<String> {
+        self.rev.clone()
+    }
+
+    fn checkout(&self) -> Result<Checkout> {
+        let dir = TempDir::new("porteurbars")?;
+        let commit = git::clone(
+            self.url.clone(),
+            dir.path(),
+            self.rev.as_deref(),
+            self.base.as_deref(),
+            self.offline,
+        )?;
+        Ok(Checkout {
+            path: dir.path().to_path_buf(),
+            commit: Some(commit),
+            repository: Some(self.url.clone()),
+            dir: Some(dir),
+        })
+    }
+}
+
+/// A template directory on the local filesystem, used in place
+#[derive(Debug, Clone)]
+pub struct Directory(pub PathBuf);
+
+impl TemplateSource for Directory {
+    fn uri(&self) -> String {
+        fs::canonicalize(&self.0)
+            .unwrap_or_else(|_| self.0.clone())
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    fn checkout(&self) -> Result<Checkout> {
+        Ok(Checkout {
+            path: fs::canonicalize(&self.0).chain_err(|| {
+                format!("template directory {} not found", self.0.display())
+            })?,
+            commit: None,
+            repository: None,
+            dir: None,
+        })
+    }
+}
+
+/// A `.tar.gz` or `.zip` template archive on the local filesystem
+#[derive(Debug, Clone)]
+pub struct Archive(pub PathBuf);
+
+impl TemplateSource for Archive {
+    fn uri(&self) -> String {
+        fs::canonicalize(&self.0)
+            .unwrap_or_else(|_| self.0.clone())
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    fn checkout(&self) -> Result<Checkout> {
+        let dir = TempDir::new("porteurbars")?;
+        Ok(Checkout {
+            path: archive::extract(&self.0, dir.path())?,
+            commit: None,
+            repository: None,
+            dir: Some(dir),
+        })
+    }
+}
+
+/// the source of a template uri. git sources check out a revision and
+/// base directory, when given
+pub fn from_url(
+    url: Url,
+    rev: Option<&str>,
+    base: Option<&str>,
+    offline: bool,
+) -> Box<dyn TemplateSource> {
+    match url {
+        Url::Directory(path) => Box::new(Directory(path.into())),
+        Url::Archive(path) => Box::new(Archive(path.into())),
+        url => Box::new(Git {
+            url,
+            rev: rev.map(str::to_owned),
+            base: base.map(str::to_owned),
+            offline,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_directory_checkout_is_used_in_place() {
+        let dir = TempDir::new("porteurbars-test").unwrap();
+        let checkout = Directory(dir.path().to_path_buf()).checkout().unwrap();
+        assert_eq!(checkout.path, fs::canonicalize(dir.path()).unwrap());
+        assert!(checkout.dir.is_none());
+        assert!(checkout.commit.is_none());
+    }
+
+    #[test]
+    fn test_missing_directory_names_path() {
+        let err = Directory("./no/such/template".into()).checkout().unwrap_err();
+        assert_eq!(err.to_string(), "template directory ./no/such/template not found");
+    }
+}
@@ -1,26 +1,133 @@
-use errors::{Result, ResultExt};
+use errors::{ErrorKind, Result, ResultExt};
 
 use case::CaseExt;
-use difference;
+use chrono::Local;
+use chrono::format::{Item, StrftimeItems};
+use diffy;
 
 use super::defaults;
+use super::helpers;
+use super::hooks;
+use super::hunks::{self, Segment};
+use super::manifest::{self, GLOB_OPTIONS, Hooks, Manifest, Variable};
+use super::metadata::Metadata;
+use super::git::{Reference, Url};
+use super::source::{self, Checkout, Directory, TemplateSource};
 use difference::{Changeset, Difference};
-use handlebars::{Handlebars, Helper, RenderContext, RenderError, Renderable};
+use handlebars::{Handlebars, Helper, HelperDef, HelperResult, JsonRender, RenderContext,
+                 RenderError, Renderable};
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use regex::Regex;
+use serde_json::Value as Json;
 use std::collections::BTreeMap;
 use std::env;
-use std::fs::{self, File, OpenOptions, create_dir_all};
-use std::io::{self, Read, Write};
-use std::path::{MAIN_SEPARATOR, Path, PathBuf};
+use std::fmt;
+use std::fs::{self, create_dir_all};
+use std::io::{self, Write};
+use std::path::{Component, MAIN_SEPARATOR, Path, PathBuf};
+use std::process::Command;
+use std::str;
+use std::sync::{Arc, Mutex, PoisonError};
+use tempdir::TempDir;
+use uuid::Builder;
 use walkdir::WalkDir;
 extern crate term;
 
 /// file to clone template to
 // const TMP_PREFIX: &'static str = "porteurbars";
 /// subdirectory containing template source
-const TEMPLATE_DIR: &'static str = "template";
+const TEMPLATE_DIR: &str = "template";
+
+/// subdirectory containing partials registered with handlebars
+const PARTIALS_DIR: &str = "partials";
 
 /// name of file containing key/value pairs representing template defaults
-const DEFAULTS: &'static str = "default.env";
+const DEFAULTS: &str = "default.env";
+
+/// name of file describing template variables in more detail than defaults
+const MANIFEST: &str = "porteurbars.toml";
+
+/// number of lines of context displayed around hunks
+const HUNK_CONTEXT: usize = 3;
+
+/// number of leading bytes of a file inspected for NUL bytes when
+/// determining if a file is binary
+const BINARY_SNIFF_LEN: usize = 8000;
+
+/// digits of the random_hex helper
+const HEX: &[u8] = b"0123456789abcdef";
+
+/// A change applied, or in the case of a dry run planned, to a file
+/// in a target directory. Paths are relative to the target directory
+#[derive(Debug, PartialEq)]
+pub enum Change {
+    /// file did not exist and was created
+    Create(PathBuf),
+    /// file existed with different content and was replaced
+    Overwrite(PathBuf),
+    /// file existed with identical content
+    Unchanged(PathBuf),
+    /// file existed with different content which was kept
+    Conflict(PathBuf),
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (action, path) = match *self {
+            Change::Create(ref path) => ("create", path),
+            Change::Overwrite(ref path) => ("overwrite", path),
+            Change::Unchanged(ref path) => ("unchanged", path),
+            Change::Conflict(ref path) => ("conflict", path),
+        };
+        write!(f, "{:>9} {}", action, path.display())
+    }
+}
+
+/// Options for applying a template
+#[derive(Debug, Default, Clone)]
+pub struct Options {
+    /// accept default values rather than prompting for them
+    pub yes: bool,
+    /// keep local copies of files rather than prompting to replace them
+    pub keep: bool,
+    /// render every path and file without writing anything to the target
+    pub dry_run: bool,
+    /// run template hooks without asking for confirmation
+    pub trust_hooks: bool,
+    /// use previously cached copies of included templates without fetching
+    pub offline: bool,
+    /// values taking precedence over the environment and defaults
+    pub answers: BTreeMap<String, String>,
+    /// environment variables providing values ahead of prompts and
+    /// defaults, typically those of the current process
+    pub env: BTreeMap<String, String>,
+    /// seed for helpers generating random values, such as uuid, making
+    /// their output reproducible
+    pub seed: Option<u64>,
+}
+
+/// The outcome of applying a template
+#[derive(Debug)]
+pub struct Applied {
+    /// resolved values of template variables
+    pub context: BTreeMap<String, String>,
+    /// changes made, or planned, to files in the target directory
+    pub changes: Vec<Change>,
+    /// commits included templates fetched from git resolved to, keyed by
+    /// their uri and base directory
+    pub includes: BTreeMap<String, String>,
+}
+
+/// Templates rendered to a staging directory
+struct Staged {
+    /// resolved values of the last template's variables
+    context: BTreeMap<String, String>,
+    /// commits included templates fetched from git resolved to
+    includes: BTreeMap<String, String>,
+    /// hooks of each template rendered, with the context they run in
+    hooks: Vec<(Hooks, BTreeMap<String, String>)>,
+}
 
 /// A template holds a path to template source and a
 /// file describing the default values associated with
@@ -29,6 +136,16 @@ const DEFAULTS: &'static str = "default.env";
 pub struct Template {
     /// path to template source
     pub path: PathBuf,
+    /// id of the commit checked out, for templates fetched from git
+    pub commit: Option<String>,
+    /// url of the repository checked out, for templates fetched from git
+    repository: Option<Url>,
+    /// uri of the template source, recorded in targets it's applied to
+    uri: String,
+    /// revision of the template source requested, for templates in git
+    rev: Option<String>,
+    /// checkout owned by this template, removed when it's dropped
+    _checkout: Option<TempDir>,
 }
 
 impl Template {
@@ -36,23 +153,82 @@ impl Template {
     where
         P: AsRef<Path>,
     {
-        Template { path: path.as_ref().to_path_buf() }
+        Template {
+            path: path.as_ref().to_path_buf(),
+            commit: None,
+            repository: None,
+            uri: Directory(path.as_ref().to_path_buf()).uri(),
+            rev: None,
+            _checkout: None,
+        }
     }
 
-    /// resolve context
-    fn context<R>(
-        &self,
-        root: &Option<R>,
-        yes: bool,
-    ) -> Result<BTreeMap<String, String>>
+    /// Fetch a template from a source. the template owns its checkout,
+    /// which is removed when the template is dropped
+    pub fn fetch<S>(source: &S) -> Result<Template>
+    where
+        S: TemplateSource + ?Sized,
+    {
+        let Checkout {
+            path,
+            commit,
+            repository,
+            dir,
+        } = source.checkout()?;
+        Ok(Template {
+            path,
+            commit,
+            repository,
+            uri: source.uri(),
+            rev: source.rev(),
+            _checkout: dir,
+        })
+    }
+
+    /// resolves the directory within this template to use as its base,
+    /// failing when it does not look like a template
+    fn base<R>(&self, root: Option<R>) -> Result<PathBuf>
     where
         R: AsRef<Path>,
     {
-        let path = &self.path;
-        let defaults_file = root.as_ref()
-            .map(|r| path.join(r))
-            .unwrap_or(path.to_path_buf())
-            .join(DEFAULTS);
+        let base = root.as_ref().map_or_else(
+            || self.path.clone(),
+            |r| self.path.join(r),
+        );
+        let invalid = |reason: &str| {
+            let path = root.as_ref().map_or_else(
+                || base.to_string_lossy().into_owned(),
+                |r| r.as_ref().to_string_lossy().into_owned(),
+            );
+            Err(ErrorKind::InvalidBase(path, reason.to_owned()).into())
+        };
+        if !base.is_dir() {
+            return invalid("directory does not exist");
+        }
+        if !base.join(TEMPLATE_DIR).is_dir() {
+            return invalid(&format!("missing {}/ directory", TEMPLATE_DIR));
+        }
+        if !base.join(DEFAULTS).is_file() && !base.join(MANIFEST).is_file() {
+            return invalid(
+                &format!("missing {} or {} file", DEFAULTS, MANIFEST),
+            );
+        }
+        Ok(base)
+    }
+
+    /// manifest of the template at a given base directory.
+    /// a manifest file takes precedence over a flat defaults file
+    fn manifest(&self, base: &Path) -> Result<Manifest> {
+        let manifest_file = base.join(MANIFEST);
+        if manifest_file.exists() {
+            return manifest::from_file(&manifest_file).chain_err(move || {
+                format!(
+                    "failed to parse manifest from file {}",
+                    manifest_file.to_string_lossy()
+                )
+            });
+        }
+        let defaults_file = base.join(DEFAULTS);
         let defaults = defaults::from_file(defaults_file.clone()).chain_err(
             move || {
                 format!(
@@ -61,50 +237,333 @@ impl Template {
                 )
             },
         )?;
-        let resolved = if yes {
-            defaults.iter().fold(BTreeMap::new(), |mut a, e| {
-                let &(ref k, ref v) = e;
-                a.insert(k.clone(), v.clone());
-                a
-            })
-        } else {
-            interact(&defaults).chain_err(|| "failed to parse defaults")?
-        };
+        Ok(Manifest {
+            variables: defaults
+                .into_iter()
+                .map(|(k, v)| Variable::from_default(k, v))
+                .collect(),
+            ..Manifest::default()
+        })
+    }
+
+    /// resolve context. values are taken from answers, then the
+    /// environment of `options`, then prompts or defaults
+    fn context(
+        &self,
+        manifest: &Manifest,
+        options: &Options,
+    ) -> Result<BTreeMap<String, String>> {
+        let mut resolved = options.answers.clone();
+        for var in &manifest.variables {
+            let provided = options
+                .answers
+                .get(&var.name)
+                .or_else(|| options.env.get(&var.name))
+                .cloned();
+            let default = var.default.as_ref().map_or("", |d| d.as_ref());
+            let value = match provided {
+                Some(value) => validate(var, &value)?,
+                None if options.yes => validate(var, default)?,
+                None => ask(var).chain_err(|| "failed to parse defaults")?,
+            };
+            resolved.insert(var.name.clone(), value);
+        }
         Ok(resolved)
     }
 
-    /// Apply template
+    /// Apply template, returning the resolved context and the
+    /// changes made to `target`. unless in a dry run, the template is
+    /// recorded in the target's metadata so it may later be updated
     pub fn apply<P, R>(
         &self,
         target: P,
         root: Option<R>,
-        yes: bool,
-        keep: bool,
-    ) -> Result<()>
+        options: &Options,
+    ) -> Result<Applied>
     where
         P: AsRef<Path>,
         R: AsRef<Path>,
     {
-        let ctx = self.context(&root, yes)?;
-        let adjusted_path = root.as_ref()
-            .map(|r| self.path.join(r))
-            .unwrap_or(self.path.to_path_buf());
+        let stage = TempDir::new("porteurbars-stage")?;
+        let staged = self.compose(
+            stage.path(),
+            root.as_ref(),
+            options,
+            &BTreeMap::new(),
+            &mut Vec::new(),
+        )?;
+        let target = target.as_ref();
+        let all = Hooks {
+            pre: staged.hooks.iter().flat_map(|(h, _)| h.pre.clone()).collect(),
+            post: staged.hooks.iter().flat_map(|(h, _)| h.post.clone()).collect(),
+        };
+        let run_hooks = if options.dry_run || all.is_empty() {
+            false
+        } else if options.trust_hooks {
+            true
+        } else if options.yes {
+            warn!("skipping template hooks. use --trust-hooks to run them");
+            false
+        } else {
+            hooks::confirm(&all)?
+        };
 
-        // apply handlebars processing
-        let apply = |path: &Path, hbs: &mut Handlebars| -> Result<()> {
+        if !options.dry_run {
+            create_dir_all(target)?;
+        }
+        if run_hooks {
+            for (hooks, ctx) in &staged.hooks {
+                hooks::run(&hooks.pre, target, ctx)?;
+            }
+        }
+        let applied = Applied {
+            changes: place(stage.path(), target, options)?,
+            context: staged.context,
+            includes: staged.includes,
+        };
+        if !options.dry_run {
+            self.record(target, root, &applied)?;
+        }
+        if run_hooks {
+            for (hooks, ctx) in &staged.hooks {
+                hooks::run(&hooks.post, target, ctx)?;
+            }
+        }
+        Ok(applied)
+    }
 
-            // /tmp/download_dir/templates
-            let scratchpath =
-                &format!(
-                    "{}{}",
-                    adjusted_path.join(TEMPLATE_DIR).to_str().unwrap(),
-                    MAIN_SEPARATOR
-                )
-                    [..];
+    /// records this template, as applied to `target`, in the target's
+    /// metadata
+    fn record<R>(&self, target: &Path, root: Option<R>, applied: &Applied) -> Result<()>
+    where
+        R: AsRef<Path>,
+    {
+        Metadata {
+            url: self.uri.clone(),
+            rev: self.rev.clone(),
+            commit: self.commit.clone(),
+            base: root.map(|root| root.as_ref().to_string_lossy().into_owned()),
+            context: applied.context.clone(),
+            includes: applied.includes.clone(),
+        }.write(target)
+    }
 
+    /// Render this template's includes and then the template itself to a
+    /// staging directory, with files of later templates replacing those of
+    /// earlier ones. answers resolved by each template are shared with
+    /// those rendered after it. includes keyed in `pins` are checked out at
+    /// the commits they map to. `seen` holds the includes being rendered,
+    /// guarding against templates including themselves
+    fn compose<R>(
+        &self,
+        stage: &Path,
+        root: Option<R>,
+        options: &Options,
+        pins: &BTreeMap<String, String>,
+        seen: &mut Vec<String>,
+    ) -> Result<Staged>
+    where
+        R: AsRef<Path>,
+    {
+        let base = self.base(root.as_ref())?;
+        let manifest = self.manifest(&base)?;
+        let mut answers = options.answers.clone();
+        let mut includes = BTreeMap::new();
+        let mut hooks = Vec::new();
+        for include in &manifest.includes {
+            let reference = Reference::from_str(&include.url)?;
+            let mut rev = include.rev.clone().or(reference.rev);
+            let mut include_base = include.base.clone().or(reference.base);
+            let url = match (reference.url, &self.repository) {
+                // relative includes of templates fetched from git are other
+                // directories of the same repository, at the same commit. only
+                // the including template's directory is checked out
+                (Url::Directory(ref path), Some(repository))
+                    if Path::new(path).is_relative() => {
+                    let within = root.as_ref()
+                        .map_or_else(PathBuf::new, |r| r.as_ref().to_path_buf())
+                        .join(path)
+                        .join(include_base.as_deref().unwrap_or(""));
+                    let within = normalize(&within).ok_or_else(|| {
+                        ErrorKind::InvalidBase(
+                            include.url.clone(),
+                            "directory is outside of the template's repository".to_owned(),
+                        )
+                    })?;
+                    include_base = Some(within).filter(|within| !within.is_empty());
+                    rev = rev.or_else(|| self.commit.clone());
+                    repository.clone()
+                }
+                // local includes are relative to the including template
+                (Url::Directory(path), _) => {
+                    Url::Directory(base.join(path).to_string_lossy().into_owned())
+                }
+                (Url::Archive(path), _) => {
+                    Url::Archive(base.join(path).to_string_lossy().into_owned())
+                }
+                (url, _) => url,
+            };
+            let include_base = include_base.as_deref();
+            let source =
+                source::from_url(url.clone(), rev.as_deref(), include_base, options.offline);
+            let key = format!("{}//{}", source.uri(), include_base.unwrap_or(""));
+            if seen.contains(&key) {
+                bail!("template {} includes itself", include.url);
+            }
+            let source = match pins.get(&key) {
+                Some(commit) => {
+                    source::from_url(url, Some(commit), include_base, options.offline)
+                }
+                None => source,
+            };
+            debug!("including template {}", key);
+            let template = Template::fetch(&*source).chain_err(|| {
+                format!("failed to fetch included template {}", include.url)
+            })?;
+            if let Some(commit) = template.commit.clone() {
+                includes.insert(key.clone(), commit);
+            }
+            seen.push(key);
+            let staged = template.compose(
+                stage,
+                include_base,
+                &Options {
+                    answers: answers.clone(),
+                    ..options.clone()
+                },
+                pins,
+                seen,
+            )?;
+            seen.pop();
+            answers.extend(staged.context);
+            includes.extend(staged.includes);
+            hooks.extend(staged.hooks);
+        }
+        let context = self.render(
+            stage,
+            &base,
+            &manifest,
+            &Options {
+                answers,
+                ..options.clone()
+            },
+        )?;
+        hooks.push((manifest.hooks, context.clone()));
+        Ok(Staged {
+            context,
+            includes,
+            hooks,
+        })
+    }
+
+    /// Update a target previously generated by a `previous` template,
+    /// with a `previous_context` and its includes at `previous_includes`
+    /// commits, to the output of this template. changes between the output
+    /// of both templates are merged with local changes made to files in
+    /// `target`, whose metadata then records this template
+    pub fn update<P, R>(
+        &self,
+        previous: &Template,
+        previous_context: &BTreeMap<String, String>,
+        previous_includes: &BTreeMap<String, String>,
+        target: P,
+        root: Option<R>,
+        options: &Options,
+    ) -> Result<Applied>
+    where
+        P: AsRef<Path>,
+        R: AsRef<Path>,
+    {
+        let base = TempDir::new("porteurbars")?;
+        previous.compose(
+            base.path(),
+            root.as_ref(),
+            &Options {
+                yes: true,
+                offline: options.offline,
+                answers: previous_context.clone(),
+                env: options.env.clone(),
+                ..Options::default()
+            },
+            previous_includes,
+            &mut Vec::new(),
+        )?;
+        let next = TempDir::new("porteurbars")?;
+        let rendered = self.compose(
+            next.path(),
+            root.as_ref(),
+            options,
+            &BTreeMap::new(),
+            &mut Vec::new(),
+        )?;
+
+        let mut changes = Vec::new();
+        for entry in WalkDir::new(next.path()).into_iter().filter_map(
+            |e| e.ok(),
+        )
+        {
+            if entry.path().is_dir() {
+                continue;
+            }
+            let relpath = entry.path().strip_prefix(next.path()).unwrap_or(
+                entry.path(),
+            );
+            debug!("updating {:?}", relpath.display());
+            let theirs = fs::read(entry.path())?;
+            let base = fs::read(base.path().join(relpath)).ok();
+            if let Some(change) = merge(
+                relpath,
+                base.as_ref().map(|b| &b[..]),
+                &theirs,
+                target.as_ref(),
+                options,
+            )?
+            {
+                changes.push(change)
+            }
+        }
+        let applied = Applied {
+            context: rendered.context,
+            changes,
+            includes: rendered.includes,
+        };
+        if !options.dry_run {
+            self.record(target.as_ref(), root, &applied)?;
+        }
+        Ok(applied)
+    }
+
+    /// Render this template alone to a staging directory, replacing files
+    /// already staged, returning its resolved context
+    fn render(
+        &self,
+        stage: &Path,
+        adjusted_path: &Path,
+        manifest: &Manifest,
+        options: &Options,
+    ) -> Result<BTreeMap<String, String>> {
+        let ctx = self.context(manifest, options)?;
+        let conditions = manifest.conditions()?;
+
+        // /tmp/download_dir/templates
+        let scratchpath = adjusted_path.join(TEMPLATE_DIR);
+
+        // apply handlebars processing
+        let apply = |path: &Path, hbs: &mut Handlebars| -> Result<()> {
             // path relatived based on scratch dir
-            let localpath =
-                path.to_str().unwrap().trim_left_matches(scratchpath);
+            let relpath = path.strip_prefix(&scratchpath).unwrap_or(path);
+            let localpath = relpath.to_string_lossy();
+
+            // skip paths whose conditions are not met
+            for (pattern, condition) in &conditions {
+                let matched = relpath
+                    .ancestors()
+                    .any(|p| pattern.matches_path_with(p, GLOB_OPTIONS));
+                if matched && !truthy(&hbs.render_template(condition, &ctx)?) {
+                    debug!("skipping {} on condition {}", localpath, condition);
+                    return Ok(());
+                }
+            }
 
             // eval path as template
             let evalpath =
@@ -112,167 +571,911 @@ impl Template {
                     format!("failed to render template {}", localpath)
                 })?;
 
-            // rewritten path, based on target dir and eval path
-            let targetpath = target.as_ref().join(evalpath);
+            // paths with a segment that evaluates to empty are skipped
+            // along with anything beneath them
+            if excluded(&evalpath) {
+                debug!("skipping excluded path {}", localpath);
+                return Ok(());
+            }
+
+            // rewritten path, based on staging dir and eval path
+            let stagepath = stage.join(&evalpath);
 
             if path.is_dir() {
-                fs::create_dir_all(targetpath).chain_err(|| {
+                return fs::create_dir_all(stagepath).chain_err(|| {
                     format!(
                         "failed to create directory {}",
                         path.to_string_lossy()
                     )
-                })?
-            } else {
-                let mut file = File::open(path)?;
-                let mut s = String::new();
-                file.read_to_string(&mut s)?;
-                if targetpath.exists() {
-                    // open file for reading and writing
-                    let mut file = OpenOptions::new()
-                        .write(true)
-                        .read(true)
-                        .open(&targetpath)?;
-
-                    // get the current content
-                    let mut current_content = String::new();
-                    file.read_to_string(&mut current_content)?;
-
-                    // get the target content
-                    let template_eval = hbs.render_template(&s, &ctx)?;
-
-                    // if there's a diff prompt for change
-                    if template_eval != current_content {
-                        let kept = keep ||
-                            keep_current_content(
-                                current_content.as_ref(),
-                                template_eval.as_ref(),
-                                &targetpath,
-                            )?;
-                        if !kept {
-                            // force truncation of current content
-                            let mut file = OpenOptions::new()
-                                .write(true)
-                                .truncate(true)
-                                .open(targetpath)?;
-                            file.write_all(template_eval.as_bytes())?;
-                        }
-                    }
-                } else {
-                    let mut file = File::create(targetpath)?;
-                    hbs.render_template_to_write(&s, &ctx, &mut file)?;
-                }
+                });
             }
+
+            // binary files are copied byte for byte
+            let content = fs::read(path)?;
+            let template_eval = if is_binary(&content) {
+                content
+            } else {
+                let s = String::from_utf8(content).chain_err(|| {
+                    format!("failed to read template {}", localpath)
+                })?;
+                hbs.render_template(&s, &ctx)?.into_bytes()
+            };
+            fs::write(&stagepath, template_eval)?;
             Ok(())
         };
 
-        create_dir_all(target.as_ref())?;
-        let mut hbs = bars();
-        for entry in WalkDir::new(&adjusted_path.join(TEMPLATE_DIR))
+        let mut hbs = bars(options.seed);
+        partials(&mut hbs, &adjusted_path.join(PARTIALS_DIR))?;
+        let helpers_file = adjusted_path.join(helpers::FILE);
+        if helpers_file.exists() {
+            let declared = helpers::from_file(&helpers_file).chain_err(|| {
+                format!(
+                    "failed to parse helpers from file {}",
+                    helpers_file.to_string_lossy()
+                )
+            })?;
+            for helper in declared {
+                // declared helpers may not silently replace built in ones
+                // or each other
+                if hbs.get_helper(&helper.name).is_some() {
+                    bail!(
+                        "helper {} declared in {} is already defined. choose another name",
+                        helper.name,
+                        helpers_file.display()
+                    );
+                }
+                debug!("registering helper {}", helper.name);
+                let name = helper.name.clone();
+                transform(&mut hbs, &name, move |value| helper.apply(value));
+            }
+        }
+        for entry in WalkDir::new(&scratchpath)
             .into_iter()
             .skip(1)
             .filter_map(|e| e.ok())
         {
-            debug!("applying {:?}", entry.path().display());
-            apply(entry.path(), &mut hbs)?
+            debug!("rendering {:?}", entry.path().display());
+            apply(entry.path(), &mut hbs)?;
         }
-        Ok(())
+        Ok(ctx)
     }
 }
 
-pub fn bars() -> Handlebars {
-    let mut hbs = Handlebars::new();
-    fn transform<F>(bars: &mut Handlebars, name: &str, f: F)
-    where
-        F: 'static + Fn(&str) -> String + Sync + Send,
+/// registers each file in a directory as a partial named after its
+/// path without its extension, such as `{{> licenses/mit}}`
+fn partials(hbs: &mut Handlebars, dir: &Path) -> Result<()> {
+    if !dir.is_dir() {
+        return Ok(());
+    }
+    for entry in WalkDir::new(dir).into_iter().filter_map(|e| e.ok()) {
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let relpath = entry.path().strip_prefix(dir).unwrap_or(entry.path());
+        let name = relpath
+            .with_extension("")
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+            .collect::<Vec<_>>()
+            .join("/");
+        debug!("registering partial {}", name);
+        let content = fs::read_to_string(entry.path())?;
+        hbs.register_partial(&name, content).chain_err(|| {
+            format!("failed to register partial {}", relpath.display())
+        })?;
+    }
+    Ok(())
+}
+
+/// registers a helper transforming a string
+fn transform<F>(bars: &mut Handlebars, name: &str, f: F)
+where
+    F: 'static + Fn(&str) -> String + Sync + Send,
+{
+    let helper_name = name.to_owned();
+    bars.register_helper(
+        name,
+        Box::new(move |h: &Helper,
+              _: &Handlebars,
+              rc: &mut RenderContext|
+              -> ::std::result::Result<(), RenderError> {
+            let value = h.param(0)
+                .and_then(|v| v.value().as_str().to_owned())
+                .ok_or(RenderError::new(format!(
+                    "Parameter 0 with str type is required for {} helper.",
+                    helper_name
+                )))?;
+            rc.writer.write_all(f(value).as_bytes())?;
+            Ok(())
+        }),
+    );
+}
+
+/// A helper computing a value from its params, so that it may be used
+/// in subexpressions such as `{{#each (split tags ",") as |tag|}}`
+struct Value<F>(F);
+
+impl<F> HelperDef for Value<F>
+where
+    F: Fn(&Helper) -> RenderResult<Json> + Send + Sync,
+{
+    fn call_inner(
+        &self,
+        h: &Helper,
+        _: &Handlebars,
+        _: &mut RenderContext,
+    ) -> RenderResult<Option<Json>> {
+        (self.0)(h).map(Some)
+    }
+}
+
+/// A helper testing its params. as a block it renders its template when
+/// the test passes and its inverse otherwise. as a subexpression it
+/// evaluates to a boolean, as in `{{#if (contains name "-")}}`
+struct Test<F>(F);
+
+impl<F> HelperDef for Test<F>
+where
+    F: Fn(&Helper) -> RenderResult<bool> + Send + Sync,
+{
+    fn call_inner(
+        &self,
+        h: &Helper,
+        _: &Handlebars,
+        _: &mut RenderContext,
+    ) -> RenderResult<Option<Json>> {
+        (self.0)(h).map(|passed| Some(Json::Bool(passed)))
+    }
+
+    fn call(&self, h: &Helper, r: &Handlebars, rc: &mut RenderContext) -> HelperResult {
+        let passed = (self.0)(h)?;
+        if !h.is_block() {
+            rc.writer.write_all(passed.to_string().as_bytes())?;
+            return Ok(());
+        }
+        match if passed { h.template() } else { h.inverse() } {
+            Some(t) => t.render(r, rc),
+            None => Ok(()),
+        }
+    }
+}
+
+type RenderResult<T> = ::std::result::Result<T, RenderError>;
+
+/// a helper's param at an index
+fn param<'a>(h: &'a Helper, idx: usize) -> RenderResult<&'a Json> {
+    h.param(idx).map(|v| v.value()).ok_or_else(|| {
+        RenderError::new(format!(
+            "Parameter {} is required for {} helper.",
+            idx,
+            h.name()
+        ))
+    })
+}
+
+/// a helper's str param at an index
+fn str_param<'a>(h: &'a Helper, idx: usize) -> RenderResult<&'a str> {
+    h.param(idx).and_then(|v| v.value().as_str()).ok_or_else(|| {
+        RenderError::new(format!(
+            "Parameter {} with str type is required for {} helper.",
+            idx,
+            h.name()
+        ))
+    })
+}
+
+/// a helper's optional str param at an index
+fn opt_str_param<'a>(h: &'a Helper, idx: usize) -> RenderResult<Option<&'a str>> {
+    match h.param(idx) {
+        Some(_) => str_param(h, idx).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// a helper's integer param at an index. as variables are strings,
+/// strings of digits are accepted too
+fn usize_param(h: &Helper, idx: usize) -> RenderResult<usize> {
+    h.param(idx)
+        .and_then(|v| match *v.value() {
+            Json::Number(ref n) => n.as_u64().map(|n| n as usize),
+            Json::String(ref s) => s.trim().parse().ok(),
+            _ => None,
+        })
+        .ok_or_else(|| {
+            RenderError::new(format!(
+                "Parameter {} with integer type is required for {} helper.",
+                idx,
+                h.name()
+            ))
+        })
+}
+
+/// splits a name into words separated by non alphanumeric characters
+/// or changes from lower to upper case, as in `fooBar` or `foo_bar`
+fn words(value: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut boundary = false;
+    for c in value.chars() {
+        if !c.is_alphanumeric() {
+            if !word.is_empty() {
+                words.push(::std::mem::take(&mut word));
+            }
+            boundary = false;
+            continue;
+        }
+        if boundary && c.is_uppercase() {
+            words.push(::std::mem::take(&mut word));
+        }
+        boundary = c.is_lowercase() || c.is_numeric();
+        word.push(c);
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+/// upper cases the first character of a word
+fn capitalized(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// true if a word ends with a suffix, ignoring ascii case
+fn ends_with(word: &str, suffix: &str) -> bool {
+    word.len().checked_sub(suffix.len()).is_some_and(|at| {
+        word.is_char_boundary(at) && word[at..].eq_ignore_ascii_case(suffix)
+    })
+}
+
+/// words whose plural is the same as their singular
+const INVARIANTS: &[&str] = &["series", "species", "news", "sheep", "fish", "deer"];
+
+/// plurals ending in ies of words ending in ie
+const IE_PLURALS: &[&str] = &[
+    "calories", "cookies", "genies", "lies", "movies", "pies", "rookies", "ties", "zombies",
+];
+
+/// true if a word is one of a list of words, ignoring ascii case
+fn one_of(word: &str, words: &[&str]) -> bool {
+    words.iter().any(|w| w.eq_ignore_ascii_case(word))
+}
+
+/// the plural of an english word, following its most common rules.
+/// irregular plurals, such as children, are not known
+fn plural(word: &str) -> String {
+    let consonant_y = ends_with(word, "y") &&
+        word.chars().rev().nth(1).is_some_and(|c| {
+            !"aeiou".contains(c.to_ascii_lowercase())
+        });
+    if word.is_empty() || one_of(word, INVARIANTS) {
+        word.to_owned()
+    } else if consonant_y {
+        format!("{}ies", &word[..word.len() - 1])
+    } else if ["s", "x", "z", "ch", "sh"].iter().any(
+        |suffix| ends_with(word, suffix),
+    )
     {
-        let helper_name = name.to_owned();
-        bars.register_helper(
-            name,
-            Box::new(move |h: &Helper,
-                  _: &Handlebars,
-                  rc: &mut RenderContext|
-                  -> ::std::result::Result<(), RenderError> {
-                let value = h.param(0)
-                    .and_then(|v| v.value().as_str().to_owned())
-                    .ok_or(RenderError::new(format!(
-                        "Parameter 0 with str type is required for {} helper.",
-                        helper_name
-                    )))?;
-                rc.writer.write(f(value).as_bytes())?;
-                Ok(())
-            }),
-        );
+        format!("{}es", word)
+    } else {
+        format!("{}s", word)
+    }
+}
+
+/// the singular of an english word, following its most common rules.
+/// irregular plurals, such as children, are not known
+fn singular(word: &str) -> String {
+    if one_of(word, INVARIANTS) {
+        word.to_owned()
+    } else if one_of(word, IE_PLURALS) {
+        word[..word.len() - 1].to_owned()
+    } else if ends_with(word, "ies") && word.len() > 3 {
+        format!("{}y", &word[..word.len() - 3])
+    } else if ["sses", "xes", "zes", "ches", "shes"].iter().any(
+        |suffix| ends_with(word, suffix),
+    )
+    {
+        word[..word.len() - 2].to_owned()
+    } else if ends_with(word, "s") && !ends_with(word, "ss") {
+        word[..word.len() - 1].to_owned()
+    } else {
+        word.to_owned()
     }
+}
 
+/// handlebars with porteurbars' helpers. helpers generating random values
+/// do so from a seed, when given, so that templates render the same way
+/// every time
+pub fn bars(seed: Option<u64>) -> Handlebars {
+    let mut hbs = Handlebars::new();
     transform(&mut hbs, "upper", str::to_uppercase);
     transform(&mut hbs, "lower", str::to_lowercase);
     transform(&mut hbs, "capitalize", CaseExt::to_capitalized);
     transform(&mut hbs, "camel", CaseExt::to_camel);
     transform(&mut hbs, "snake", CaseExt::to_snake);
     transform(&mut hbs, "dashed", CaseExt::to_dashed);
+    transform(&mut hbs, "trim", |v| v.trim().to_owned());
+    transform(&mut hbs, "pascal", |v| {
+        words(v)
+            .iter()
+            .map(|w| capitalized(&w.to_lowercase()))
+            .collect()
+    });
+    transform(&mut hbs, "kebab", |v| words(v).join("-").to_lowercase());
+    transform(&mut hbs, "screaming_snake", |v| {
+        words(v).join("_").to_uppercase()
+    });
+    transform(&mut hbs, "title", |v| {
+        words(v)
+            .iter()
+            .map(|w| capitalized(w))
+            .collect::<Vec<_>>()
+            .join(" ")
+    });
+    transform(&mut hbs, "plural", plural);
+    transform(&mut hbs, "singular", singular);
+    transform(&mut hbs, "dirname", |v| {
+        Path::new(v)
+            .parent()
+            .map(|p| p.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    });
+    transform(&mut hbs, "basename", |v| {
+        Path::new(v)
+            .file_name()
+            .map(|p| p.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    });
+    hbs.register_helper(
+        "replace",
+        Box::new(Value(|h: &Helper| {
+            Ok(Json::String(
+                str_param(h, 0)?.replace(str_param(h, 1)?, str_param(h, 2)?),
+            ))
+        })),
+    );
+    hbs.register_helper(
+        "truncate",
+        Box::new(Value(|h: &Helper| {
+            Ok(Json::String(
+                str_param(h, 0)?.chars().take(usize_param(h, 1)?).collect(),
+            ))
+        })),
+    );
+    // pads the end of a value to a width, with spaces or a given character
+    hbs.register_helper(
+        "pad",
+        Box::new(Value(|h: &Helper| {
+            let value = str_param(h, 0)?;
+            let width = usize_param(h, 1)?;
+            let fill = opt_str_param(h, 2)?
+                .and_then(|fill| fill.chars().next())
+                .unwrap_or(' ');
+            let padding = width.saturating_sub(value.chars().count());
+            Ok(Json::String(
+                value.chars().chain(::std::iter::repeat_n(fill, padding)).collect(),
+            ))
+        })),
+    );
+    hbs.register_helper(
+        "split",
+        Box::new(Value(|h: &Helper| {
+            Ok(Json::Array(
+                str_param(h, 0)?
+                    .split(str_param(h, 1)?)
+                    .map(|item| Json::String(item.to_owned()))
+                    .collect(),
+            ))
+        })),
+    );
+    hbs.register_helper(
+        "join",
+        Box::new(Value(|h: &Helper| {
+            let separator = str_param(h, 1)?;
+            Ok(Json::String(match *param(h, 0)? {
+                Json::Array(ref items) => {
+                    items
+                        .iter()
+                        .map(JsonRender::render)
+                        .collect::<Vec<_>>()
+                        .join(separator)
+                }
+                ref value => value.render(),
+            }))
+        })),
+    );
     // helper for eq(quality)
     hbs.register_helper(
         "eq",
-        Box::new(|h: &Helper,
-         r: &Handlebars,
-         rc: &mut RenderContext|
-         -> ::std::result::Result<(), RenderError> {
-            let a = h.param(0).and_then(|v| v.value().as_str()).ok_or(
-                RenderError::new(
-                    "Parameter 0 with str type is required for eq helper.",
-                ),
-            )?;
-            let b = h.param(1).and_then(|v| v.value().as_str()).ok_or(
-                RenderError::new(
-                    "Parameter 1 with str type is required for eq helper.",
-                ),
-            )?;
-            let tmpl = if a == b { h.template() } else { h.inverse() };
-            match tmpl {
-                Some(ref t) => t.render(r, rc),
-                None => Ok(()),
+        Box::new(Test(|h: &Helper| Ok(str_param(h, 0)? == str_param(h, 1)?))),
+    );
+    hbs.register_helper(
+        "ne",
+        Box::new(Test(|h: &Helper| Ok(str_param(h, 0)? != str_param(h, 1)?))),
+    );
+    hbs.register_helper(
+        "and",
+        Box::new(Test(|h: &Helper| {
+            param(h, 0)?;
+            Ok(h.params().iter().all(|p| truthy(&p.value().render())))
+        })),
+    );
+    hbs.register_helper(
+        "or",
+        Box::new(Test(|h: &Helper| {
+            param(h, 0)?;
+            Ok(h.params().iter().any(|p| truthy(&p.value().render())))
+        })),
+    );
+    hbs.register_helper(
+        "not",
+        Box::new(Test(|h: &Helper| Ok(!truthy(&param(h, 0)?.render())))),
+    );
+    // tests strings for substrings and lists, such as those split, for items
+    hbs.register_helper(
+        "contains",
+        Box::new(Test(|h: &Helper| {
+            let needle = str_param(h, 1)?;
+            Ok(match *param(h, 0)? {
+                Json::Array(ref items) => items.iter().any(|item| item.render() == needle),
+                ref value => value.render().contains(needle),
+            })
+        })),
+    );
+    hbs.register_helper(
+        "starts_with",
+        Box::new(Test(|h: &Helper| {
+            Ok(str_param(h, 0)?.starts_with(str_param(h, 1)?))
+        })),
+    );
+    hbs.register_helper(
+        "ends_with",
+        Box::new(Test(|h: &Helper| {
+            Ok(str_param(h, 0)?.ends_with(str_param(h, 1)?))
+        })),
+    );
+    // patterns are compiled once, rather than each time they're matched
+    let regexes = Mutex::new(BTreeMap::new());
+    hbs.register_helper(
+        "matches",
+        Box::new(Test(move |h: &Helper| {
+            let pattern = str_param(h, 1)?;
+            let mut regexes = regexes.lock().unwrap_or_else(PoisonError::into_inner);
+            if !regexes.contains_key(pattern) {
+                let regex = Regex::new(pattern).map_err(|e| {
+                    RenderError::new(format!("invalid pattern {} for matches helper: {}", pattern, e))
+                })?;
+                regexes.insert(pattern.to_owned(), regex);
             }
-        }),
+            Ok(regexes[pattern].is_match(str_param(h, 0)?))
+        })),
+    );
+    // the current local time, formatted with strftime syntax
+    hbs.register_helper(
+        "now",
+        Box::new(Value(|h: &Helper| {
+            let format = str_param(h, 0)?;
+            if StrftimeItems::new(format).any(|item| item == Item::Error) {
+                return Err(RenderError::new(
+                    format!("invalid format {} for now helper", format),
+                ));
+            }
+            Ok(Json::String(Local::now().format(format).to_string()))
+        })),
+    );
+    // a variable of the environment at render time, or a default
+    hbs.register_helper(
+        "env",
+        Box::new(Value(|h: &Helper| {
+            let default = opt_str_param(h, 1)?.unwrap_or_default();
+            Ok(Json::String(
+                env::var(str_param(h, 0)?).unwrap_or_else(|_| default.to_owned()),
+            ))
+        })),
+    );
+    let rng = Arc::new(Mutex::new(match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    }));
+    // handlebars only calls helpers given params, so uuids are named.
+    // each name renders the same uuid wherever it's used
+    let (uuid_rng, uuids) = (rng.clone(), Mutex::new(BTreeMap::new()));
+    hbs.register_helper(
+        "uuid",
+        Box::new(Value(move |h: &Helper| {
+            let name = str_param(h, 0)?;
+            let mut uuids = uuids.lock().unwrap_or_else(PoisonError::into_inner);
+            let uuid = uuids.entry(name.to_owned()).or_insert_with(|| {
+                let bytes = uuid_rng.lock().unwrap_or_else(PoisonError::into_inner).gen();
+                Builder::from_random_bytes(bytes).into_uuid().to_string()
+            });
+            Ok(Json::String(uuid.clone()))
+        })),
+    );
+    hbs.register_helper(
+        "random_hex",
+        Box::new(Value(move |h: &Helper| {
+            let len = usize_param(h, 0)?;
+            let mut rng = rng.lock().unwrap_or_else(PoisonError::into_inner);
+            Ok(Json::String(
+                (0..len).map(|_| HEX[rng.gen_range(0..HEX.len())] as char).collect(),
+            ))
+        })),
     );
-
 
     hbs
 }
 
-fn keep_current_content<P>(
-    current: &str,
-    new: &str,
+/// writes the files of templates rendered to a staging directory to a
+/// target, prompting for how to resolve those differing from what's there
+fn place(stage: &Path, target: &Path, options: &Options) -> Result<Vec<Change>> {
+    let mut changes = Vec::new();
+    for entry in WalkDir::new(stage)
+        .sort_by(|a, b| a.file_name().cmp(b.file_name()))
+        .into_iter()
+        .skip(1)
+        .filter_map(|e| e.ok())
+    {
+        let relpath = entry.path().strip_prefix(stage).unwrap_or(entry.path());
+        let targetpath = target.join(relpath);
+        debug!("applying {:?}", relpath.display());
+
+        if entry.file_type().is_dir() {
+            if !options.dry_run {
+                fs::create_dir_all(&targetpath).chain_err(|| {
+                    format!("failed to create directory {}", targetpath.display())
+                })?
+            }
+            continue;
+        }
+
+        let template_eval = fs::read(entry.path())?;
+        if !targetpath.exists() {
+            if !options.dry_run {
+                fs::write(&targetpath, template_eval)?;
+            }
+            changes.push(Change::Create(relpath.into()));
+            continue;
+        }
+
+        // get the current content
+        let current_content = fs::read(&targetpath)?;
+
+        if template_eval == current_content {
+            changes.push(Change::Unchanged(relpath.into()));
+            continue;
+        }
+
+        // if there's a diff prompt for change. dry runs can't ask, so plan
+        // to replace the file
+        if options.keep {
+            changes.push(Change::Conflict(relpath.into()));
+        } else if options.dry_run {
+            changes.push(Change::Overwrite(relpath.into()));
+        } else {
+            changes.push(
+                match resolve_hunks(&current_content, &template_eval, &targetpath)? {
+                    Some(content) => {
+                        fs::write(&targetpath, content)?;
+                        Change::Overwrite(relpath.into())
+                    }
+                    None => Change::Conflict(relpath.into()),
+                },
+            );
+        }
+    }
+    Ok(changes)
+}
+
+/// merges the content of a file rendered by an updated template, `theirs`,
+/// with the content rendered by the previous template, `base`, and local
+/// content within a target directory
+fn merge(
+    relpath: &Path,
+    base: Option<&[u8]>,
+    theirs: &[u8],
+    target: &Path,
+    options: &Options,
+) -> Result<Option<Change>> {
+    let targetpath = target.join(relpath);
+    let write = |content: &[u8]| -> Result<()> {
+        if !options.dry_run {
+            if let Some(parent) = targetpath.parent() {
+                create_dir_all(parent)?;
+            }
+            fs::write(&targetpath, content)?;
+        }
+        Ok(())
+    };
+
+    if !targetpath.exists() {
+        // files removed locally stay removed
+        if base.is_some() {
+            return Ok(None);
+        }
+        write(theirs)?;
+        return Ok(Some(Change::Create(relpath.into())));
+    }
+
+    let ours = fs::read(&targetpath)?;
+    if ours == theirs || base == Some(theirs) {
+        return Ok(Some(Change::Unchanged(relpath.into())));
+    }
+    if base == Some(&ours[..]) {
+        write(theirs)?;
+        return Ok(Some(Change::Overwrite(relpath.into())));
+    }
+
+    match base {
+        Some(base) if !is_binary(base) && !is_binary(&ours) &&
+                          !is_binary(theirs) => {
+            let merged = diffy::merge(
+                &String::from_utf8_lossy(base),
+                &String::from_utf8_lossy(&ours),
+                &String::from_utf8_lossy(theirs),
+            );
+            match merged {
+                Ok(merged) => {
+                    write(merged.as_bytes())?;
+                    Ok(Some(Change::Overwrite(relpath.into())))
+                }
+                Err(conflicted) => {
+                    if !options.keep {
+                        write(conflicted.as_bytes())?;
+                    }
+                    Ok(Some(Change::Conflict(relpath.into())))
+                }
+            }
+        }
+        _ => {
+            let resolved = if options.keep || options.dry_run {
+                None
+            } else {
+                resolve_hunks(&ours, theirs, &targetpath)?
+            };
+            match resolved {
+                Some(content) => {
+                    write(&content)?;
+                    Ok(Some(Change::Overwrite(relpath.into())))
+                }
+                None => Ok(Some(Change::Conflict(relpath.into()))),
+            }
+        }
+    }
+}
+
+/// a rendered condition is considered met when it is
+/// neither empty nor false
+fn truthy(value: &str) -> bool {
+    let value = value.trim();
+    !value.is_empty() && value != "false"
+}
+
+/// lexically resolves the `.` and `..` components of a relative path,
+/// joined with `/`. paths resolving outside of their root have none
+fn normalize(path: &Path) -> Option<String> {
+    let mut parts = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir => {
+                parts.pop()?;
+            }
+            Component::Normal(part) => parts.push(part.to_string_lossy().into_owned()),
+            _ => return None,
+        }
+    }
+    Some(parts.join("/"))
+}
+
+/// a rendered path is excluded when any of its segments are empty
+fn excluded(path: &str) -> bool {
+    path.split(['/', MAIN_SEPARATOR])
+        .any(|segment| segment.trim().is_empty())
+}
+
+/// files containing a NUL byte or invalid utf8 are treated
+/// as binary content and are not rendered as templates
+fn is_binary(content: &[u8]) -> bool {
+    content.iter().take(BINARY_SNIFF_LEN).any(|b| *b == 0) ||
+        str::from_utf8(content).is_err()
+}
+
+/// help displayed when resolving conflicting hunks
+const HUNK_HELP: &str = "y - accept this change
+n - reject this change, keeping current content
+e - edit this change
+a - accept this and all remaining changes
+k - keep current content for this and all remaining changes
+r - replace the whole file with the new version
+w - keep current content for remaining changes, saving the current and new
+    versions of the file alongside it with .orig and .rej extensions
+q - quit, keeping the whole file as is
+";
+
+/// prompts to resolve conflicts between the current and new content of
+/// a file, returning the resolved content or None to keep it as is
+fn resolve_hunks<P>(
+    current: &[u8],
+    new: &[u8],
     file: P,
-) -> io::Result<bool>
+) -> io::Result<Option<Vec<u8>>>
 where
     P: AsRef<Path>,
 {
-    let mut answer = String::new();
     println!(
         "\n⚠️ Warning: Conflicts exist with the previous version of {}\n",
         file.as_ref().display()
     );
-    diff(difference::Changeset::new(current, new, "\n"))?;
-    print!("Type `r` to replace it: ");
+    if is_binary(current) || is_binary(new) {
+        println!("Binary files differ\n");
+        let answer = read_answer("Type `r` to replace it: ")?;
+        return Ok(if answer == "r" { Some(new.to_vec()) } else { None });
+    }
+
+    let current = String::from_utf8_lossy(current);
+    let new = String::from_utf8_lossy(new);
+    let segments = hunks::segments(&current, &new);
+    let total = segments
+        .iter()
+        .filter(|s| matches!(**s, Segment::Hunk { .. }))
+        .count();
+    let mut choices: Vec<Option<String>> = Vec::new();
+    // remaining hunks are accepted when true, rejected when false
+    let mut remaining: Option<bool> = None;
+    for (index, segment) in segments.iter().enumerate() {
+        let (current_lines, new_lines) = match *segment {
+            Segment::Hunk {
+                ref current,
+                ref new,
+            } => (current, new),
+            _ => continue,
+        };
+        let choice = match remaining {
+            Some(true) => new_lines.clone(),
+            Some(false) => current_lines.clone(),
+            None => loop {
+                show_hunk(&segments, index)?;
+                let answer = read_answer(&format!(
+                    "({}/{}) Apply this change [y,n,e,a,k,r,w,q,?]? ",
+                    choices.len() + 1,
+                    total
+                ))?;
+                match answer.as_ref() {
+                    "y" => break new_lines.clone(),
+                    "n" | "" => break current_lines.clone(),
+                    "e" => break edit(new_lines)?,
+                    "a" => {
+                        remaining = Some(true);
+                        break new_lines.clone();
+                    }
+                    "k" => {
+                        remaining = Some(false);
+                        break current_lines.clone();
+                    }
+                    "r" => return Ok(Some(new.as_bytes().to_vec())),
+                    "w" => {
+                        fs::write(side_file(&file, "orig"), current.as_bytes())?;
+                        fs::write(side_file(&file, "rej"), new.as_bytes())?;
+                        remaining = Some(false);
+                        break current_lines.clone();
+                    }
+                    "q" | "\u{4}" => return Ok(None),
+                    _ => print!("{}", HUNK_HELP),
+                }
+            },
+        };
+        choices.push(choice);
+    }
+    let resolved = hunks::join(&segments, &choices);
+    Ok(if resolved == current {
+        None
+    } else {
+        Some(resolved.into_bytes())
+    })
+}
+
+/// prints a prompt and reads a trimmed, lowercased answer.
+/// end of input is answered as ^D
+fn read_answer(prompt: &str) -> io::Result<String> {
+    let mut answer = String::new();
+    print!("{}", prompt);
     io::stdout().flush()?;
-    io::stdin().read_line(&mut answer)?;
-    let trimmed = answer.trim().to_lowercase();
-    Ok(trimmed.is_empty() || trimmed != String::from("r"))
+    if io::stdin().read_line(&mut answer)? == 0 {
+        return Ok("\u{4}".to_owned());
+    }
+    Ok(answer.trim().to_lowercase())
 }
 
-fn diff(changes: difference::Changeset) -> io::Result<()> {
-    let Changeset { diffs, .. } = changes;
+/// path of a file alongside another, with an additional extension
+fn side_file<P>(file: P, extension: &str) -> PathBuf
+where
+    P: AsRef<Path>,
+{
+    let mut path = file.as_ref().as_os_str().to_owned();
+    path.push(".");
+    path.push(extension);
+    path.into()
+}
+
+/// displays a hunk with a few lines of surrounding context
+fn show_hunk(segments: &[Segment], index: usize) -> io::Result<()> {
+    let mut diffs = Vec::new();
+    if let Some(Segment::Same(before)) =
+        index.checked_sub(1).and_then(|i| segments.get(i))
+    {
+        let lines = before.lines().collect::<Vec<_>>();
+        let start = lines.len().saturating_sub(HUNK_CONTEXT);
+        diffs.push(Difference::Same(lines[start..].join("\n")));
+    }
+    if let Segment::Hunk {
+        ref current,
+        ref new,
+    } = segments[index]
+    {
+        if let Some(ref current) = *current {
+            diffs.push(Difference::Rem(current.clone()));
+        }
+        if let Some(ref new) = *new {
+            diffs.push(Difference::Add(new.clone()));
+        }
+    }
+    if let Some(Segment::Same(after)) = segments.get(index + 1) {
+        let lines = after.lines().take(HUNK_CONTEXT).collect::<Vec<_>>();
+        diffs.push(Difference::Same(lines.join("\n")));
+    }
+    println!();
+    diff(&diffs)
+}
+
+/// opens a hunk's lines in the user's editor, returning the edited lines
+fn edit(lines: &Option<String>) -> io::Result<Option<String>> {
+    let dir = TempDir::new("porteurbars")?;
+    let path = dir.path().join("hunk");
+    fs::write(&path, lines.as_ref().map_or("", |l| l.as_ref()))?;
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_owned());
+    let mut words = editor.split_whitespace();
+    let status = Command::new(words.next().unwrap_or("vi"))
+        .args(words)
+        .arg(&path)
+        .status()?;
+    if !status.success() {
+        return Err(io::Error::other(
+            format!("editor {} failed with {}", editor, status),
+        ));
+    }
+    let edited = fs::read_to_string(&path)?;
+    let edited = edited.strip_suffix('\n').unwrap_or(&edited);
+    Ok(if edited.is_empty() {
+        None
+    } else {
+        Some(edited.to_owned())
+    })
+}
+
+fn diff(diffs: &[Difference]) -> io::Result<()> {
     let mut t = term::stdout().unwrap();
 
     for i in 0..diffs.len() {
         match diffs[i] {
             Difference::Same(ref x) => {
                 t.reset()?;
-                writeln!(t, " {}", x)?;
+                for line in x.lines() {
+                    writeln!(t, " {}", line)?;
+                }
             }
             Difference::Add(ref x) => {
-                match diffs[i - 1] {
-                    Difference::Rem(ref y) => {
+                match i.checked_sub(1).map(|i| &diffs[i]) {
+                    // changes to a single line highlight what changed within it
+                    Some(Difference::Rem(y))
+                        if !y.contains('\n') && !x.contains('\n') =>
+                    {
                         t.fg(term::color::GREEN)?;
                         write!(t, "+")?;
                         let Changeset { diffs, .. } = Changeset::new(y, x, "");
@@ -293,17 +1496,21 @@ fn diff(changes: difference::Changeset) -> io::Result<()> {
                                 _ => (),
                             }
                         }
-                        writeln!(t, "")?;
+                        writeln!(t)?;
                     }
                     _ => {
                         t.fg(term::color::BRIGHT_GREEN)?;
-                        writeln!(t, "+{}", x)?;
+                        for line in x.split('\n') {
+                            writeln!(t, "+{}", line)?;
+                        }
                     }
                 };
             }
             Difference::Rem(ref x) => {
                 t.fg(term::color::RED)?;
-                writeln!(t, "-{}", x)?;
+                for line in x.split('\n') {
+                    writeln!(t, "-{}", line)?;
+                }
             }
         }
     }
@@ -315,9 +1522,18 @@ fn diff(changes: difference::Changeset) -> io::Result<()> {
 /// prompt for a value defaulting to a given string when an answer is not available
 fn prompt(name: &str, default: &str) -> io::Result<String> {
     let mut answer = String::new();
-    print!("{} [{}]: ", name, default);
+    if default.is_empty() {
+        print!("{}: ", name);
+    } else {
+        print!("{} [{}]: ", name, default);
+    }
     io::stdout().flush()?;
-    io::stdin().read_line(&mut answer)?;
+    if io::stdin().read_line(&mut answer)? == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!("no answer provided for {}", name),
+        ));
+    }
     let trimmed = answer.trim();
     if trimmed.trim().is_empty() {
         Ok(default.to_owned())
@@ -326,19 +1542,30 @@ fn prompt(name: &str, default: &str) -> io::Result<String> {
     }
 }
 
-/// given a set of defaults, attempt to interact with a user
-/// to resolve the parameters that can not be inferred from env
-fn interact(defaults: &defaults::Defaults) -> Result<BTreeMap<String, String>> {
-    let mut resolved = BTreeMap::new();
-    for pair in defaults.iter() {
-        let &(ref k, ref v) = pair;
-        let answer = match env::var(k) {
-            Ok(v) => v,
-            _ => prompt(k.as_ref(), v.as_ref())?,
-        };
-        resolved.insert(k.clone(), answer);
+/// validates a value for a variable
+fn validate(var: &Variable, value: &str) -> Result<String> {
+    var.validate(value).map_err(|reason| {
+        ErrorKind::InvalidValue(var.name.clone(), reason).into()
+    })
+}
+
+/// prompts for a variable's value until a valid answer is provided
+fn ask(var: &Variable) -> io::Result<String> {
+    if let Some(ref description) = var.description {
+        println!("{}", description);
+    }
+    let label = match var.hint() {
+        Some(hint) => format!("{} ({})", var.name, hint),
+        _ => var.name.clone(),
+    };
+    let default = var.default.as_ref().map_or("", |d| d.as_ref());
+    loop {
+        let answer = prompt(&label, default)?;
+        match var.validate(&answer) {
+            Ok(value) => return Ok(value),
+            Err(reason) => println!("{}", reason),
+        }
     }
-    Ok(resolved)
 }
 
 #[cfg(test)]
@@ -346,6 +1573,540 @@ mod tests {
     use super::*;
     use std::collections::BTreeMap;
 
+    fn yes() -> Options {
+        Options {
+            yes: true,
+            ..Options::default()
+        }
+    }
+
+    fn dry_run() -> Options {
+        Options {
+            dry_run: true,
+            ..yes()
+        }
+    }
+
+    /// writes a template with the given defaults and template files
+    fn fixture(defaults: &str, files: &[(&str, &str)]) -> TempDir {
+        let dir = TempDir::new("porteurbars-test").unwrap();
+        fs::write(dir.path().join(DEFAULTS), defaults).unwrap();
+        for &(path, content) in files {
+            let path = dir.path().join(TEMPLATE_DIR).join(path);
+            create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn apply_dry_run_writes_nothing() {
+        let template =
+            fixture("NAME=bars", &[("{{NAME}}/hello", "hi {{NAME}}")]);
+        let target = TempDir::new("porteurbars-target").unwrap();
+        let out = target.path().join("out");
+        let changes = Template::new(template.path())
+            .apply(&out, None::<&str>, &dry_run())
+            .unwrap()
+            .changes;
+        assert_eq!(
+            changes,
+            vec![Change::Create(PathBuf::from("bars/hello"))]
+        );
+        assert!(!out.exists());
+    }
+
+    #[test]
+    fn apply_dry_run_reports_existing_files() {
+        let template = fixture(
+            "NAME=bars",
+            &[("same", "hi {{NAME}}"), ("changed", "hi {{NAME}}")],
+        );
+        let target = TempDir::new("porteurbars-target").unwrap();
+        fs::write(target.path().join("same"), "hi bars").unwrap();
+        fs::write(target.path().join("changed"), "bye bars").unwrap();
+        let mut changes = Template::new(template.path())
+            .apply(target.path(), None::<&str>, &dry_run())
+            .unwrap()
+            .changes;
+        changes.sort_by_key(|c| c.to_string());
+        assert_eq!(
+            changes,
+            vec![
+                Change::Overwrite(PathBuf::from("changed")),
+                Change::Unchanged(PathBuf::from("same")),
+            ]
+        );
+        assert_eq!(
+            "bye bars",
+            fs::read_to_string(target.path().join("changed")).unwrap()
+        );
+    }
+
+    #[test]
+    fn apply_dry_run_reports_kept_files_as_conflicts() {
+        let template = fixture("NAME=bars", &[("changed", "hi {{NAME}}")]);
+        let target = TempDir::new("porteurbars-target").unwrap();
+        fs::write(target.path().join("changed"), "bye bars").unwrap();
+        let changes = Template::new(template.path())
+            .apply(
+                target.path(),
+                None::<&str>,
+                &Options {
+                    keep: true,
+                    ..dry_run()
+                },
+            )
+            .unwrap()
+            .changes;
+        assert_eq!(changes, vec![Change::Conflict(PathBuf::from("changed"))]);
+    }
+
+    #[test]
+    fn apply_rejects_invalid_base() {
+        let template = fixture("name=bars", &[("hello", "hi {{name}}")]);
+        let target = TempDir::new("porteurbars-target").unwrap();
+        let apply = |root: &str| {
+            Template::new(template.path())
+                .apply(target.path(), Some(root), &yes())
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(
+            apply("missing"),
+            "invalid template base directory missing: directory does not exist"
+        );
+        assert_eq!(
+            apply(TEMPLATE_DIR),
+            "invalid template base directory template: missing template/ directory"
+        );
+        create_dir_all(template.path().join("empty").join(TEMPLATE_DIR))
+            .unwrap();
+        assert_eq!(
+            apply("empty"),
+            "invalid template base directory empty: missing default.env or porteurbars.toml file"
+        );
+    }
+
+    #[test]
+    fn apply_includes_templates_sharing_answers() {
+        let base = fixture("name=base\nlicense=MIT", &[("LICENSE", "{{license}}")]);
+        let template = fixture("", &[("README", "{{name}} {{lang}}")]);
+        fs::write(
+            template.path().join(MANIFEST),
+            format!(
+                "[[variable]]\nname = \"lang\"\ndefault = \"rust\"\n\n\
+                 [[include]]\nurl = \"{}\"\n",
+                base.path().display()
+            ),
+        ).unwrap();
+        let target = TempDir::new("porteurbars-target").unwrap();
+        let applied = Template::new(template.path())
+            .apply(target.path(), None::<&str>, &yes())
+            .unwrap();
+        assert_eq!(
+            applied.changes,
+            vec![
+                Change::Create(PathBuf::from("LICENSE")),
+                Change::Create(PathBuf::from("README")),
+            ]
+        );
+        assert_eq!(applied.context.get("license"), Some(&"MIT".to_owned()));
+        assert_eq!(
+            "base rust",
+            fs::read_to_string(target.path().join("README")).unwrap()
+        );
+    }
+
+    #[test]
+    fn apply_prefers_files_of_including_templates() {
+        let base = fixture("", &[("README", "base"), ("LICENSE", "MIT")]);
+        let template = fixture("", &[("README", "template")]);
+        fs::write(
+            template.path().join(MANIFEST),
+            format!("[[include]]\nurl = \"{}\"\n", base.path().display()),
+        ).unwrap();
+        let target = TempDir::new("porteurbars-target").unwrap();
+        let expected = vec![
+            Change::Create(PathBuf::from("LICENSE")),
+            Change::Create(PathBuf::from("README")),
+        ];
+        let template = Template::new(template.path());
+        let planned = template
+            .apply(target.path(), None::<&str>, &dry_run())
+            .unwrap();
+        assert_eq!(planned.changes, expected);
+        let applied = template
+            .apply(
+                target.path(),
+                None::<&str>,
+                &Options {
+                    keep: true,
+                    ..yes()
+                },
+            )
+            .unwrap();
+        assert_eq!(applied.changes, expected);
+        assert_eq!(
+            "template",
+            fs::read_to_string(target.path().join("README")).unwrap()
+        );
+    }
+
+    #[test]
+    fn apply_rejects_cyclic_includes() {
+        let template = fixture("", &[("README", "hi")]);
+        fs::write(
+            template.path().join(MANIFEST),
+            "[[include]]\nurl = \".\"\n",
+        ).unwrap();
+        let target = TempDir::new("porteurbars-target").unwrap();
+        let err = Template::new(template.path())
+            .apply(target.path(), None::<&str>, &dry_run())
+            .unwrap_err();
+        assert_eq!(err.to_string(), "template . includes itself");
+    }
+
+    #[test]
+    fn apply_renders_partials() {
+        let template = fixture(
+            "name=bars",
+            &[("main.rs", "{{> licenses/header}}fn main() {}")],
+        );
+        let licenses = template.path().join(PARTIALS_DIR).join("licenses");
+        create_dir_all(&licenses).unwrap();
+        fs::write(licenses.join("header.hbs"), "// (c) {{name}}\n").unwrap();
+        let target = TempDir::new("porteurbars-target").unwrap();
+        Template::new(template.path())
+            .apply(target.path(), None::<&str>, &yes())
+            .unwrap();
+        assert_eq!(
+            "// (c) bars\nfn main() {}",
+            fs::read_to_string(target.path().join("main.rs")).unwrap()
+        );
+    }
+
+    #[test]
+    fn apply_registers_declared_helpers() {
+        let template = fixture("name=my-app", &[("out", "{{crate_name name}}")]);
+        fs::write(
+            template.path().join(helpers::FILE),
+            "[[helper]]\nname = \"crate_name\"\n\
+             replace = [{ pattern = \"-\", with = \"_\" }]\n",
+        ).unwrap();
+        let target = TempDir::new("porteurbars-target").unwrap();
+        Template::new(template.path())
+            .apply(target.path(), None::<&str>, &yes())
+            .unwrap();
+        assert_eq!(
+            "my_app",
+            fs::read_to_string(target.path().join("out")).unwrap()
+        );
+    }
+
+    #[test]
+    fn apply_rejects_declared_helpers_named_like_defined_ones() {
+        for name in &["upper", "if"] {
+            let template = fixture("", &[("out", "hi")]);
+            fs::write(
+                template.path().join(helpers::FILE),
+                format!("[[helper]]\nname = \"{}\"\n", name),
+            ).unwrap();
+            let target = TempDir::new("porteurbars-target").unwrap();
+            let err = Template::new(template.path())
+                .apply(target.path(), None::<&str>, &yes())
+                .unwrap_err();
+            assert!(err.to_string().starts_with(
+                &format!("helper {} declared in", name)
+            ));
+        }
+    }
+
+    #[test]
+    fn is_binary_detects_nul_bytes() {
+        assert!(is_binary(b"\x89PNG\r\n\x1a\n\x00\x00"));
+        assert!(is_binary(&[0xff, 0xfe, 0xfd]));
+        assert!(!is_binary("hello {{NAME}} ✓".as_bytes()));
+    }
+
+    #[test]
+    fn apply_copies_binary_files() {
+        let template = fixture("NAME=bars", &[]);
+        let binary = b"{{NAME}}\x00\xff";
+        let icons = template.path().join(TEMPLATE_DIR).join("{{NAME}}");
+        create_dir_all(&icons).unwrap();
+        fs::write(icons.join("favicon.ico"), &binary[..]).unwrap();
+        let target = TempDir::new("porteurbars-target").unwrap();
+        Template::new(template.path())
+            .apply(target.path(), None::<&str>, &yes())
+            .unwrap();
+        assert_eq!(
+            &binary[..],
+            &fs::read(target.path().join("bars/favicon.ico")).unwrap()[..]
+        );
+    }
+
+    #[test]
+    fn apply_prefers_manifest_to_defaults() {
+        let template = fixture("NAME=bars", &[("hello", "hi {{NAME}}")]);
+        fs::write(
+            template.path().join(MANIFEST),
+            "[[variable]]\nname = \"NAME\"\ndefault = \"manifest\"\n",
+        ).unwrap();
+        let target = TempDir::new("porteurbars-target").unwrap();
+        Template::new(template.path())
+            .apply(target.path(), None::<&str>, &yes())
+            .unwrap();
+        assert_eq!(
+            "hi manifest",
+            fs::read_to_string(target.path().join("hello")).unwrap()
+        );
+    }
+
+    #[test]
+    fn apply_requires_values_for_required_variables() {
+        let template = fixture("", &[("hello", "hi {{NAME}}")]);
+        fs::write(
+            template.path().join(MANIFEST),
+            "[[variable]]\nname = \"NAME\"\nrequired = true\n",
+        ).unwrap();
+        let target = TempDir::new("porteurbars-target").unwrap();
+        assert!(
+            Template::new(template.path())
+                .apply(target.path(), None::<&str>, &yes())
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn excluded_paths() {
+        assert!(excluded(""));
+        assert!(excluded("/config.yml"));
+        assert!(excluded("ci//config.yml"));
+        assert!(!excluded("ci/config.yml"));
+    }
+
+    #[test]
+    fn apply_skips_paths_rendered_empty() {
+        let template = fixture(
+            "NAME=bars\nDOCKER=",
+            &[("{{DOCKER}}/run.sh", "run"), ("{{NAME}}", "hi")],
+        );
+        let target = TempDir::new("porteurbars-target").unwrap();
+        let changes = Template::new(template.path())
+            .apply(target.path(), None::<&str>, &yes())
+            .unwrap()
+            .changes;
+        assert_eq!(changes, vec![Change::Create(PathBuf::from("bars"))]);
+    }
+
+    #[test]
+    fn apply_skips_paths_with_unmet_conditions() {
+        let template = fixture(
+            "",
+            &[
+                ("Dockerfile", "FROM scratch"),
+                ("docker/run.sh", "run"),
+                ("ci/config.yml", "ci"),
+            ],
+        );
+        fs::write(
+            template.path().join(MANIFEST),
+            r#"
+[[variable]]
+name = "docker"
+type = "bool"
+default = false
+
+[[variable]]
+name = "ci"
+type = "bool"
+default = true
+
+[conditions]
+"Dockerfile" = "{{docker}}"
+"docker" = "{{docker}}"
+"ci/*.yml" = "{{ci}}"
+"#,
+        ).unwrap();
+        let target = TempDir::new("porteurbars-target").unwrap();
+        let changes = Template::new(template.path())
+            .apply(target.path(), None::<&str>, &yes())
+            .unwrap()
+            .changes;
+        assert_eq!(
+            changes,
+            vec![Change::Create(PathBuf::from("ci/config.yml"))]
+        );
+        assert!(!target.path().join("Dockerfile").exists());
+        assert!(!target.path().join("docker").exists());
+    }
+
+    #[test]
+    fn apply_runs_trusted_hooks() {
+        let template = fixture("", &[("hello", "hi")]);
+        fs::write(
+            template.path().join(MANIFEST),
+            r#"
+[[variable]]
+name = "NAME"
+default = "bars"
+
+[hooks]
+pre = ["echo $NAME > pre"]
+post = ["cat hello pre > post"]
+"#,
+        ).unwrap();
+        let target = TempDir::new("porteurbars-target").unwrap();
+        Template::new(template.path())
+            .apply(
+                target.path(),
+                None::<&str>,
+                &Options {
+                    trust_hooks: true,
+                    ..yes()
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            "hibars\n",
+            fs::read_to_string(target.path().join("post")).unwrap()
+        );
+    }
+
+    #[test]
+    fn apply_skips_untrusted_hooks_without_prompts() {
+        let template = fixture("", &[("hello", "hi")]);
+        fs::write(
+            template.path().join(MANIFEST),
+            "[hooks]\npost = [\"touch hooked\"]\n",
+        ).unwrap();
+        let target = TempDir::new("porteurbars-target").unwrap();
+        Template::new(template.path())
+            .apply(target.path(), None::<&str>, &yes())
+            .unwrap();
+        assert!(!target.path().join("hooked").exists());
+    }
+
+    #[test]
+    fn apply_prefers_answers_to_environment() {
+        let template = fixture(
+            "name=bars\nother=default",
+            &[("hello", "{{name}} {{other}}")],
+        );
+        let target = TempDir::new("porteurbars-target").unwrap();
+        let mut answers = BTreeMap::new();
+        answers.insert("name".to_owned(), "answered".to_owned());
+        let mut env = BTreeMap::new();
+        env.insert("name".to_owned(), "exported".to_owned());
+        env.insert("other".to_owned(), "exported".to_owned());
+        Template::new(template.path())
+            .apply(
+                target.path(),
+                None::<&str>,
+                &Options {
+                    answers,
+                    env,
+                    ..yes()
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            "answered exported",
+            fs::read_to_string(target.path().join("hello")).unwrap()
+        );
+    }
+
+    #[test]
+    fn update_merges_template_and_local_changes() {
+        let previous = fixture(
+            "name=bars",
+            &[
+                ("untouched", "v1"),
+                ("edited", "one\ntwo\nthree\nfour\nfive\n"),
+                ("conflicted", "{{name}} v1\n"),
+            ],
+        );
+        let next = fixture(
+            "name=bars",
+            &[
+                ("untouched", "v2"),
+                ("edited", "one\ntwo\nthree\nfour\nfive!\n"),
+                ("conflicted", "{{name}} v2\n"),
+                ("added", "new"),
+            ],
+        );
+        let target = TempDir::new("porteurbars-target").unwrap();
+        let applied = Template::new(previous.path())
+            .apply(target.path(), None::<&str>, &yes())
+            .unwrap();
+        fs::write(
+            target.path().join("edited"),
+            "one!\ntwo\nthree\nfour\nfive\n",
+        ).unwrap();
+        fs::write(target.path().join("conflicted"), "bars local\n").unwrap();
+
+        let mut changes = Template::new(next.path())
+            .update(
+                &Template::new(previous.path()),
+                &applied.context,
+                &applied.includes,
+                target.path(),
+                None::<&str>,
+                &yes(),
+            )
+            .unwrap()
+            .changes;
+        changes.sort_by_key(|c| c.to_string());
+        assert_eq!(
+            changes,
+            vec![
+                Change::Create(PathBuf::from("added")),
+                Change::Conflict(PathBuf::from("conflicted")),
+                Change::Overwrite(PathBuf::from("edited")),
+                Change::Overwrite(PathBuf::from("untouched")),
+            ]
+        );
+        let read = |path| fs::read_to_string(target.path().join(path)).unwrap();
+        assert_eq!("v2", read("untouched"));
+        assert_eq!("one!\ntwo\nthree\nfour\nfive!\n", read("edited"));
+        assert!(read("conflicted").contains("<<<<<<<"));
+    }
+
+    #[test]
+    fn apply_writes_files() {
+        let template =
+            fixture("NAME=bars", &[("{{NAME}}/hello", "hi {{NAME}}")]);
+        let target = TempDir::new("porteurbars-target").unwrap();
+        let changes = Template::new(template.path())
+            .apply(target.path(), None::<&str>, &yes())
+            .unwrap()
+            .changes;
+        assert_eq!(
+            changes,
+            vec![Change::Create(PathBuf::from("bars/hello"))]
+        );
+        assert_eq!(
+            "hi bars",
+            fs::read_to_string(target.path().join("bars/hello")).unwrap()
+        );
+    }
+
+    #[test]
+    fn apply_records_metadata() {
+        let template = fixture("NAME=bars", &[("hello", "hi {{NAME}}")]);
+        let target = TempDir::new("porteurbars-target").unwrap();
+        Template::new(template.path())
+            .apply(target.path(), None::<&str>, &dry_run())
+            .unwrap();
+        assert!(!target.path().join(::metadata::FILE).exists());
+        Template::new(template.path())
+            .apply(target.path(), None::<&str>, &yes())
+            .unwrap();
+        let metadata = Metadata::read(target.path()).unwrap();
+        assert_eq!(metadata.context.get("NAME"), Some(&"bars".to_owned()));
+        assert_eq!(metadata.commit, None);
+    }
 
     #[test]
     fn bars_respects_escapes_tags() {
@@ -353,7 +2114,7 @@ mod tests {
         map.insert("name".to_owned(), "porteurbars".to_owned());
         assert_eq!(
             "Hello, {{upper name}}",
-            bars()
+            bars(None)
                 .render_template(r"Hello, \{{upper name}}", &map)
                 .unwrap()
         );
@@ -365,7 +2126,7 @@ mod tests {
         map.insert("name".to_owned(), "porteurbars".to_owned());
         assert_eq!(
             "Hello, PORTEURBARS",
-            bars()
+            bars(None)
                 .render_template("Hello, {{upper name}}", &map)
                 .unwrap()
         );
@@ -377,7 +2138,7 @@ mod tests {
         map.insert("name".to_owned(), "porteurbars".to_owned());
         assert_eq!(
             "Hello, Porteurbars",
-            bars()
+            bars(None)
                 .render_template("Hello, {{capitalize name}}", &map)
                 .unwrap()
         );
@@ -389,7 +2150,7 @@ mod tests {
         map.insert("name".to_owned(), "porteur_bars".to_owned());
         assert_eq!(
             "Hello, PorteurBars",
-            bars()
+            bars(None)
                 .render_template("Hello, {{camel name}}", &map)
                 .unwrap()
         );
@@ -401,7 +2162,7 @@ mod tests {
         map.insert("name".to_owned(), "porteur_bars".to_owned());
         assert_eq!(
             "Hello, porteur-bars",
-            bars()
+            bars(None)
                 .render_template("Hello, {{dashed name}}", &map)
                 .unwrap()
         );
@@ -413,7 +2174,7 @@ mod tests {
         map.insert("name".to_owned(), "porteurBars".to_owned());
         assert_eq!(
             "Hello, porteur_bars",
-            bars()
+            bars(None)
                 .render_template("Hello, {{snake name}}", &map)
                 .unwrap()
         );
@@ -425,7 +2186,7 @@ mod tests {
         map.insert("name".to_owned(), "PORTEURBARS".to_owned());
         assert_eq!(
             "Hello, porteurbars",
-            bars()
+            bars(None)
                 .render_template("Hello, {{lower name}}", &map)
                 .unwrap()
         );
@@ -437,7 +2198,7 @@ mod tests {
         map.insert("name".to_owned(), "foo".to_owned());
         assert_eq!(
             "Hello, you",
-            bars()
+            bars(None)
                 .render_template(r#"Hello, {{#eq name "foo"}}you{{/eq}}"#, &map)
                 .unwrap()
         );
@@ -449,7 +2210,7 @@ mod tests {
         map.insert("name".to_owned(), "bar".to_owned());
         assert_eq!(
             "Hello, bar",
-            bars()
+            bars(None)
                 .render_template(
                     r#"Hello, {{#eq name "foo"}}you{{else}}bar{{/eq}}"#,
                     &map,
@@ -457,4 +2218,261 @@ mod tests {
                 .unwrap()
         );
     }
+
+    fn rendered(template: &str, vars: &[(&str, &str)]) -> String {
+        let map = vars.iter()
+            .map(|&(k, v)| (k.to_owned(), v.to_owned()))
+            .collect::<BTreeMap<_, _>>();
+        bars(None).render_template(template, &map).unwrap()
+    }
+
+    #[test]
+    fn bars_replace() {
+        assert_eq!(
+            "my_cool_app",
+            rendered(r#"{{replace name "-" "_"}}"#, &[("name", "my-cool-app")])
+        );
+    }
+
+    #[test]
+    fn bars_trim() {
+        assert_eq!("bars", rendered("{{trim name}}", &[("name", "  bars ")]));
+    }
+
+    #[test]
+    fn bars_pascal() {
+        assert_eq!(
+            "PorteurBars PorteurBars",
+            rendered(
+                "{{pascal a}} {{pascal b}}",
+                &[("a", "porteur-bars"), ("b", "porteurBars")],
+            )
+        );
+    }
+
+    #[test]
+    fn bars_kebab() {
+        assert_eq!(
+            "porteur-bars porteur-bars",
+            rendered(
+                "{{kebab a}} {{kebab b}}",
+                &[("a", "PorteurBars"), ("b", "porteur_bars")],
+            )
+        );
+    }
+
+    #[test]
+    fn bars_screaming_snake() {
+        assert_eq!(
+            "PORTEUR_BARS",
+            rendered("{{screaming_snake name}}", &[("name", "porteurBars")])
+        );
+    }
+
+    #[test]
+    fn bars_title() {
+        assert_eq!(
+            "My Cool App",
+            rendered("{{title name}}", &[("name", "my-cool app")])
+        );
+    }
+
+    #[test]
+    fn bars_plural() {
+        assert_eq!(
+            "crates boxes categories days",
+            rendered(
+                "{{plural a}} {{plural b}} {{plural c}} {{plural d}}",
+                &[("a", "crate"), ("b", "box"), ("c", "category"), ("d", "day")],
+            )
+        );
+        assert_eq!(
+            "series movies",
+            rendered(
+                "{{plural a}} {{plural b}}",
+                &[("a", "series"), ("b", "movie")],
+            )
+        );
+    }
+
+    #[test]
+    fn bars_singular() {
+        assert_eq!(
+            "crate box category class",
+            rendered(
+                "{{singular a}} {{singular b}} {{singular c}} {{singular d}}",
+                &[("a", "crates"), ("b", "boxes"), ("c", "categories"), ("d", "class")],
+            )
+        );
+        // irregular plurals are left as is
+        assert_eq!(
+            "Series movie children",
+            rendered(
+                "{{singular a}} {{singular b}} {{singular c}}",
+                &[("a", "Series"), ("b", "movies"), ("c", "children")],
+            )
+        );
+    }
+
+    #[test]
+    fn bars_truncate() {
+        assert_eq!(
+            "porte porteurbars",
+            rendered(
+                "{{truncate name 5}} {{truncate name len}}",
+                &[("name", "porteurbars"), ("len", "20")],
+            )
+        );
+    }
+
+    #[test]
+    fn bars_pad() {
+        assert_eq!(
+            "bars  |bars..|porteurbars",
+            rendered(
+                r#"{{pad a 6}}|{{pad a 6 "."}}|{{pad b 6}}"#,
+                &[("a", "bars"), ("b", "porteurbars")],
+            )
+        );
+    }
+
+    #[test]
+    fn bars_split_join() {
+        assert_eq!(
+            "[a][b][c] a/b/c",
+            rendered(
+                r#"{{#each (split path ",") as |part|}}[{{part}}]{{/each}} {{join (split path ",") "/"}}"#,
+                &[("path", "a,b,c")],
+            )
+        );
+    }
+
+    #[test]
+    fn bars_dirname_basename() {
+        assert_eq!(
+            "src/bin main.rs",
+            rendered(
+                "{{dirname path}} {{basename path}}",
+                &[("path", "src/bin/main.rs")],
+            )
+        );
+    }
+
+    #[test]
+    fn bars_ne() {
+        assert_eq!(
+            "you",
+            rendered(r#"{{#ne name "foo"}}you{{/ne}}"#, &[("name", "bar")])
+        );
+    }
+
+    #[test]
+    fn bars_and_or_not() {
+        let vars = &[("yes", "true"), ("no", "false"), ("empty", "")];
+        assert_eq!(
+            "and",
+            rendered("{{#and yes yes}}and{{/and}}{{#and yes no}}!and{{/and}}", vars)
+        );
+        assert_eq!(
+            "or",
+            rendered("{{#or no yes}}or{{/or}}{{#or no empty}}!or{{/or}}", vars)
+        );
+        assert_eq!(
+            "not",
+            rendered("{{#not no}}not{{else}}!not{{/not}}", vars)
+        );
+        assert_eq!(
+            "if",
+            rendered("{{#if (and yes empty)}}!if{{/if}}{{#if (not no)}}if{{/if}}", vars)
+        );
+    }
+
+    #[test]
+    fn bars_contains() {
+        let vars = &[("name", "porteurbars"), ("tags", "cli,web")];
+        assert_eq!(
+            "yes no yes",
+            rendered(
+                r#"{{#contains name "bar"}}yes{{/contains}} {{#contains name "baz"}}yes{{else}}no{{/contains}} {{#if (contains (split tags ",") "web")}}yes{{/if}}"#,
+                vars,
+            )
+        );
+    }
+
+    #[test]
+    fn bars_starts_with_ends_with() {
+        assert_eq!(
+            "starts ends",
+            rendered(
+                r#"{{#starts_with name "porteur"}}starts{{/starts_with}} {{#ends_with name "bars"}}ends{{/ends_with}}"#,
+                &[("name", "porteurbars")],
+            )
+        );
+    }
+
+    #[test]
+    fn bars_matches() {
+        assert_eq!(
+            "valid",
+            rendered(
+                r#"{{#matches name "^[a-z][a-z0-9_]*$"}}valid{{else}}invalid{{/matches}}"#,
+                &[("name", "porteur_bars")],
+            )
+        );
+        assert_eq!(
+            "true",
+            rendered(r#"{{matches name "bars$"}}"#, &[("name", "porteurbars")])
+        );
+    }
+
+    #[test]
+    fn bars_now() {
+        assert_eq!(
+            ::chrono::Local::now().format("%Y").to_string(),
+            rendered(r#"{{now "%Y"}}"#, &[])
+        );
+        assert!(bars(None).render_template(r#"{{now "%Q"}}"#, &()).is_err());
+    }
+
+    #[test]
+    fn bars_env() {
+        assert_eq!(
+            format!("{} fallback", env::var("PATH").unwrap()),
+            rendered(
+                r#"{{env "PATH"}} {{env "PORTEURBARS_TEST_UNSET" "fallback"}}"#,
+                &[],
+            )
+        );
+    }
+
+    #[test]
+    fn bars_uuid() {
+        let uuids = rendered(r#"{{uuid "a"}} {{uuid "b"}} {{uuid "a"}}"#, &[]);
+        let uuids = uuids.split(' ').collect::<Vec<_>>();
+        assert_eq!(uuids[0].len(), 36);
+        assert_eq!(&uuids[0][14..15], "4");
+        assert_ne!(uuids[0], uuids[1]);
+        assert_eq!(uuids[0], uuids[2]);
+        let seeded = |seed| {
+            bars(Some(seed))
+                .render_template(r#"{{uuid "a"}}"#, &())
+                .unwrap()
+        };
+        assert_eq!(seeded(42), seeded(42));
+        assert_ne!(seeded(42), seeded(7));
+    }
+
+    #[test]
+    fn bars_random_hex() {
+        let hex = rendered("{{random_hex 32}}", &[]);
+        assert_eq!(hex.len(), 32);
+        assert!(hex.chars().all(|c| c.is_ascii_hexdigit()));
+        let seeded = |seed| {
+            bars(Some(seed))
+                .render_template("{{random_hex 8}} {{random_hex 8}}", &())
+                .unwrap()
+        };
+        assert_eq!(seeded(42), seeded(42));
+        assert_ne!(seeded(42)[..8], seeded(42)[9..]);
+    }
 }
@@ -0,0 +1,345 @@
+extern crate flate2;
+extern crate git2;
+extern crate tar;
+extern crate tempdir;
+
+use git2::{Repository, Signature};
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+use tempdir::TempDir;
+
+mod common;
+
+/// writes files to a git repository, committing them to master
+fn repository(dir: &Path, files: &[(&str, &str)]) {
+    let repo = Repository::init(dir).unwrap();
+    for &(path, content) in files {
+        let path = dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+    let mut index = repo.index().unwrap();
+    index
+        .add_all(["*"].iter(), git2::IndexAddOption::DEFAULT, None)
+        .unwrap();
+    index.write().unwrap();
+    let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+    let sig = Signature::now("porteurbars", "porteurbars@example.com").unwrap();
+    let parent = repo.refname_to_id("refs/heads/master")
+        .and_then(|id| repo.find_commit(id))
+        .ok();
+    let parents = parent.iter().collect::<Vec<_>>();
+    repo.commit(Some("refs/heads/master"), &sig, &sig, "init", &tree, &parents)
+        .unwrap();
+}
+
+/// runs porteurbars, caching templates within a given directory
+fn porteurbars(cache: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_porteurbars"))
+        .args(args)
+        .env("XDG_CACHE_HOME", cache)
+        .stdin(Stdio::null())
+        .output()
+        .unwrap()
+}
+
+fn monorepo() -> TempDir {
+    let repo = TempDir::new("porteurbars-repo").unwrap();
+    repository(
+        repo.path(),
+        &[
+            ("README.md", "templates"),
+            ("rust/default.env", "name=rusty"),
+            ("rust/template/{{name}}/Cargo.toml", "name = \"{{name}}\""),
+            ("scala/default.env", "name=scaly"),
+            ("scala/template/{{name}}/build.sbt", "name := \"{{name}}\""),
+        ],
+    );
+    repo
+}
+
+#[test]
+fn applies_template_from_base_directory() {
+    let repo = monorepo();
+    let target = TempDir::new("porteurbars-target").unwrap();
+    let cache = TempDir::new("porteurbars-cache").unwrap();
+    let url = format!("file://{}", repo.path().display());
+    let output = porteurbars(cache.path(), &[
+        &url,
+        target.path().to_str().unwrap(),
+        "--base",
+        "rust",
+        "--yes",
+    ]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        "name = \"rusty\"",
+        fs::read_to_string(target.path().join("rusty/Cargo.toml")).unwrap()
+    );
+    assert!(!target.path().join("scaly").exists());
+}
+
+#[test]
+fn rejects_invalid_base_directory() {
+    let repo = monorepo();
+    let target = TempDir::new("porteurbars-target").unwrap();
+    let cache = TempDir::new("porteurbars-cache").unwrap();
+    let url = format!("file://{}", repo.path().display());
+    let output = porteurbars(cache.path(), &[
+        &url,
+        target.path().to_str().unwrap(),
+        "--base",
+        "haskell",
+        "--yes",
+    ]);
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains(
+            "invalid template base directory haskell: directory does not exist",
+        )
+    );
+}
+
+#[test]
+fn applies_template_from_local_directory() {
+    let template = TempDir::new("porteurbars-template").unwrap();
+    fs::write(template.path().join("default.env"), "name=local").unwrap();
+    fs::create_dir_all(template.path().join("template")).unwrap();
+    fs::write(template.path().join("template/hello"), "hi {{name}}").unwrap();
+    let target = TempDir::new("porteurbars-target").unwrap();
+    let cache = TempDir::new("porteurbars-cache").unwrap();
+    let output = porteurbars(cache.path(), &[
+        template.path().to_str().unwrap(),
+        target.path().to_str().unwrap(),
+        "--yes",
+    ]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        "hi local",
+        fs::read_to_string(target.path().join("hello")).unwrap()
+    );
+}
+
+#[test]
+fn rejects_updates_of_local_directory_templates() {
+    let template = TempDir::new("porteurbars-template").unwrap();
+    fs::write(template.path().join("default.env"), "name=local").unwrap();
+    fs::create_dir_all(template.path().join("template")).unwrap();
+    fs::write(template.path().join("template/hello"), "hi {{name}}").unwrap();
+    let target = TempDir::new("porteurbars-target").unwrap();
+    let cache = TempDir::new("porteurbars-cache").unwrap();
+    let target = target.path().to_str().unwrap();
+    let output = porteurbars(
+        cache.path(),
+        &[template.path().to_str().unwrap(), target, "--yes"],
+    );
+    assert!(output.status.success());
+    let output = porteurbars(cache.path(), &["update", target, "--yes"]);
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains(
+            "is a local directory or archive. only templates in git repositories can be updated"
+        )
+    );
+}
+
+#[test]
+fn applies_cached_template_offline() {
+    let repo = monorepo();
+    let cache = TempDir::new("porteurbars-cache").unwrap();
+    let url = format!("file://{}", repo.path().display());
+    let apply = |offline: bool| {
+        let target = TempDir::new("porteurbars-target").unwrap();
+        let mut args = vec![
+            url.as_str(),
+            target.path().to_str().unwrap(),
+            "--base",
+            "rust",
+            "--yes",
+        ];
+        if offline {
+            args.push("--offline");
+        }
+        let output = porteurbars(cache.path(), &args);
+        (output, target.path().join("rusty/Cargo.toml").exists())
+    };
+    let (output, applied) = apply(true);
+    assert!(!output.status.success());
+    assert!(!applied);
+    let (output, applied) = apply(false);
+    assert!(output.status.success());
+    assert!(applied);
+    fs::remove_dir_all(repo.path()).unwrap();
+    let (output, applied) = apply(true);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(applied);
+}
+
+#[test]
+fn applies_template_from_subdirectory_in_uri() {
+    let repo = monorepo();
+    let target = TempDir::new("porteurbars-target").unwrap();
+    let cache = TempDir::new("porteurbars-cache").unwrap();
+    let uri = format!("file://{}//scala@master", repo.path().display());
+    let output = porteurbars(
+        cache.path(),
+        &[&uri, target.path().to_str().unwrap(), "--yes"],
+    );
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(target.path().join("scaly/build.sbt").exists());
+}
+
+#[test]
+fn applies_template_from_archive() {
+    let tmp = TempDir::new("porteurbars-archive").unwrap();
+    let archive = tmp.path().join("template.tar.gz");
+    common::tar_gz(
+        &archive,
+        &[
+            ("template-v1/default.env", "name=archived"),
+            ("template-v1/template/hello", "hi {{name}}"),
+        ],
+    );
+    let target = TempDir::new("porteurbars-target").unwrap();
+    let cache = TempDir::new("porteurbars-cache").unwrap();
+    let uri = format!("file://{}", archive.display());
+    let output = porteurbars(
+        cache.path(),
+        &[&uri, target.path().to_str().unwrap(), "--yes"],
+    );
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        "hi archived",
+        fs::read_to_string(target.path().join("hello")).unwrap()
+    );
+}
+
+#[test]
+fn rejects_updates_of_archive_templates() {
+    let tmp = TempDir::new("porteurbars-archive").unwrap();
+    let archive = tmp.path().join("template.tar.gz");
+    common::tar_gz(&archive, &[("default.env", ""), ("template/hello", "hi")]);
+    let target = TempDir::new("porteurbars-target").unwrap();
+    let cache = TempDir::new("porteurbars-cache").unwrap();
+    let target = target.path().to_str().unwrap();
+    let output = porteurbars(
+        cache.path(),
+        &[archive.to_str().unwrap(), target, "--yes"],
+    );
+    assert!(output.status.success());
+    let output = porteurbars(cache.path(), &["update", target, "--yes"]);
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains(
+            "is a local directory or archive. only templates in git repositories can be updated"
+        )
+    );
+}
+
+#[test]
+fn applies_git_template_including_sibling_directory() {
+    let repo = TempDir::new("porteurbars-repo").unwrap();
+    repository(
+        repo.path(),
+        &[
+            ("shared/default.env", "license=MIT"),
+            ("shared/template/LICENSE", "{{license}}"),
+            (
+                "rust/porteurbars.toml",
+                "[[variable]]\nname = \"name\"\ndefault = \"rusty\"\n\n\
+                 [[include]]\nurl = \"../shared\"\n",
+            ),
+            ("rust/template/{{name}}/Cargo.toml", "license = \"{{license}}\""),
+        ],
+    );
+    let target = TempDir::new("porteurbars-target").unwrap();
+    let cache = TempDir::new("porteurbars-cache").unwrap();
+    let uri = format!("file://{}//rust", repo.path().display());
+    let output = porteurbars(
+        cache.path(),
+        &[&uri, target.path().to_str().unwrap(), "--yes"],
+    );
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        "MIT",
+        fs::read_to_string(target.path().join("LICENSE")).unwrap()
+    );
+    assert_eq!(
+        "license = \"MIT\"",
+        fs::read_to_string(target.path().join("rusty/Cargo.toml")).unwrap()
+    );
+}
+
+#[test]
+fn updates_included_templates() {
+    let base = TempDir::new("porteurbars-base").unwrap();
+    repository(
+        base.path(),
+        &[
+            ("default.env", "license=MIT"),
+            ("template/LICENSE", "{{license}} v1\n"),
+        ],
+    );
+    let repo = TempDir::new("porteurbars-repo").unwrap();
+    repository(
+        repo.path(),
+        &[
+            (
+                "porteurbars.toml",
+                &format!("[[include]]\nurl = \"file://{}\"\n", base.path().display()),
+            ),
+            ("template/README.md", "readme"),
+        ],
+    );
+    let target = TempDir::new("porteurbars-target").unwrap();
+    let cache = TempDir::new("porteurbars-cache").unwrap();
+    let url = format!("file://{}", repo.path().display());
+    let output = porteurbars(
+        cache.path(),
+        &[&url, target.path().to_str().unwrap(), "--yes"],
+    );
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    repository(base.path(), &[("template/LICENSE", "{{license}} v2\n")]);
+    let output = porteurbars(
+        cache.path(),
+        &["update", target.path().to_str().unwrap(), "--yes"],
+    );
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        "MIT v2\n",
+        fs::read_to_string(target.path().join("LICENSE")).unwrap()
+    );
+}
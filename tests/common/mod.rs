@@ -0,0 +1,24 @@
+//! Fixtures shared by unit and integration tests
+
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use std::fs::File;
+use std::path::Path;
+use tar;
+
+/// writes a gzipped tarball of files, given as their paths within the
+/// archive and their content
+pub fn tar_gz(path: &Path, files: &[(&str, &str)]) {
+    let mut builder = tar::Builder::new(GzEncoder::new(
+        File::create(path).unwrap(),
+        Compression::default(),
+    ));
+    for &(name, content) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, name, content.as_bytes()).unwrap();
+    }
+    builder.into_inner().unwrap().finish().unwrap();
+}